@@ -133,7 +133,7 @@ impl NetworkConfig for Network {
         self.network.len()
     }
 
-    fn adjacent(&self, from: usize) -> Cow<Vec<usize>> {
+    fn adjacent(&self, from: usize) -> Cow<'_, Vec<usize>> {
         Cow::Borrowed(&self.adjacency[from])
     }
 
@@ -141,7 +141,7 @@ impl NetworkConfig for Network {
         self.network.get(from)?.get(to)?.map(Duration::from_millis)
     }
 
-    fn node(&self, _id: usize) -> Box<Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>> {
+    fn node(&self, _id: usize) -> Box<dyn Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>> {
         Box::new(PingPong {
             counter: 0,
             sleep: self.sleep,
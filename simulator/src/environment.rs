@@ -9,22 +9,27 @@ use crate::Time;
 use crate::unique_id::UniqueId;
 
 pub struct Environment<'a, E, ME> {
-    network_config: &'a NetworkConfig<EventType=E, MetricsEventType=ME>,
-    metrics: &'a mut Metrics<EventType=ME>,
+    network_config: &'a dyn NetworkConfig<EventType=E, MetricsEventType=ME>,
+    metrics: &'a mut dyn Metrics<EventType=ME>,
     queue: &'a mut BinaryHeap<Event<E>>,
     own_id: UniqueId,
     time: Time,
+    initial_time: Time,
 }
 
 impl<'a, E, ME> Environment<'a, E, ME> {
+    /// Creates the environment in which a node handles an event at `time`.
+    /// Nodes can also be driven outside of the simulator this way, e.g. in tests.
     #[inline]
-    pub(crate) fn new(own_id: UniqueId, config: &'a NetworkConfig<EventType=E, MetricsEventType=ME>, time: Time,
+    pub fn new(own_id: UniqueId, config: &'a dyn NetworkConfig<EventType=E, MetricsEventType=ME>, time: Time,
+                      initial_time: Time,
                       queue: &'a mut BinaryHeap<Event<E>>,
-                      metrics: &'a mut Metrics<EventType=ME>) -> Self {
+                      metrics: &'a mut dyn Metrics<EventType=ME>) -> Self {
         Environment {
             own_id,
             network_config: config,
             time,
+            initial_time,
             queue,
             metrics,
         }
@@ -32,7 +37,7 @@ impl<'a, E, ME> Environment<'a, E, ME> {
 
     /// Returns a slice of peers this node has.
     #[inline]
-    pub fn peers(&self) -> Cow<Vec<UniqueId>> {
+    pub fn peers(&self) -> Cow<'_, Vec<UniqueId>> {
         self.network_config.adjacent(self.own_id)
     }
 
@@ -48,7 +53,8 @@ impl<'a, E, ME> Environment<'a, E, ME> {
     /// The latency will be added automatically.
     /// Returns `true` on success and `false` on error (e.g. if no link has been found).
     pub fn schedule(&mut self, to: UniqueId, event: E, scheduled_send_time: Time) -> bool {
-        if let Some(delay) = self.network_config.full_transmission_time(self.own_id, to, &event) {
+        let elapsed = scheduled_send_time - self.initial_time;
+        if let Some(delay) = self.network_config.transmission_time_at(self.own_id, to, &event, elapsed) {
             let e = Event::new(event,
                                scheduled_send_time + delay, self.own_id, to);
             self.queue.push(e);
@@ -72,6 +78,12 @@ impl<'a, E, ME> Environment<'a, E, ME> {
         self.time
     }
 
+    /// Returns the start time of the simulation.
+    #[inline]
+    pub fn initial_time(&self) -> Time {
+        self.initial_time
+    }
+
     /// Advances time on the clock.
     #[inline]
     pub fn advance_time(&mut self, duration: Duration) {
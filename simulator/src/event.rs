@@ -69,7 +69,7 @@ impl<E> Eq for Event<E> {}
 
 impl<E> PartialOrd for Event<E> {
     fn partial_cmp(&self, other: &Event<E>) -> Option<Ordering> {
-        other.time.partial_cmp(&self.time)
+        Some(self.cmp(other))
     }
 }
 
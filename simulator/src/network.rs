@@ -13,7 +13,7 @@ pub trait NetworkConfig {
 
     /// Returns the adjacent nodes.
     /// Links are not duplex by default!
-    fn adjacent(&self, from: UniqueId) -> Cow<Vec<UniqueId>>;
+    fn adjacent(&self, from: UniqueId) -> Cow<'_, Vec<UniqueId>>;
 
     /// Returns the delay for an event sent over a link if it exists, None otherwise.
     /// Links are not duplex by default!
@@ -21,6 +21,13 @@ pub trait NetworkConfig {
     /// This is used to account for latency and transmission time.
     fn full_transmission_time(&self, from: UniqueId, to: UniqueId, event: &Self::EventType) -> Option<Duration>;
 
+    /// Returns the delay for an event sent over a link at `elapsed` since the start of the simulation.
+    ///
+    /// Defaults to `full_transmission_time` and allows networks to change their behaviour over time.
+    fn transmission_time_at(&self, from: UniqueId, to: UniqueId, event: &Self::EventType, _elapsed: Duration) -> Option<Duration> {
+        self.full_transmission_time(from, to, event)
+    }
+
     /// Returns the behavior for a node.
-    fn node(&self, id: UniqueId) -> Box<Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>>;
+    fn node(&self, id: UniqueId) -> Box<dyn Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>>;
 }
@@ -16,7 +16,7 @@ use crate::UniqueId;
 pub struct Simulator<N: NetworkConfig, M: Metrics<EventType=N::MetricsEventType>> {
     network_config: N,
     metrics: M,
-    nodes: Vec<Box<Node<EventType=N::EventType, MetricsEventType=N::MetricsEventType>>>,
+    nodes: Vec<Box<dyn Node<EventType=N::EventType, MetricsEventType=N::MetricsEventType>>>,
     queue: BinaryHeap<Event<N::EventType>>,
     initial_time: Time,
 }
@@ -70,6 +70,7 @@ impl<N: NetworkConfig, M: Metrics<EventType=N::MetricsEventType>> Simulator<N, M
                 let env = Environment::new(event.to,
                                            &self.network_config,
                                            event.receive_time(),
+                                           self.initial_time,
                                            &mut self.queue,
                                            &mut self.metrics);
                 if !recipient.run(event, env) {
@@ -122,17 +123,12 @@ impl<N: NetworkConfig, M: Metrics<EventType=N::MetricsEventType>> Future for Sim
     /// Runs the stream to completion and returns the simulator again.
     fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
         if let Some(ref mut simulator) = self.simulator {
-            loop {
-                match try_ready!(simulator.poll()) {
-                    Some(_) => (),
-                    None => break,
-                }
-            }
+            while try_ready!(simulator.poll()).is_some() {}
         }
 
         match self.simulator.take() {
-            Some(simulator) => return Ok(Async::Ready(simulator)),
-            None => return Err(()),
+            Some(simulator) => Ok(Async::Ready(simulator)),
+            None => Err(()),
         }
     }
 }
@@ -149,12 +145,17 @@ impl<N: NetworkConfig, M: Metrics<EventType=N::MetricsEventType>> Stream for Sim
         }
 
         match self.queue.pop() {
-            None => Ok(Async::Ready(None)),
+            None => {
+                // The simulation ends once a node reached its goal, which none did if no events are left.
+                error!("Event queue ran empty before the simulation finished, nodes are stuck.");
+                Ok(Async::Ready(None))
+            },
             Some(event) => {
                 if let Some(recipient) = self.nodes.get_mut(event.to) {
                     let env = Environment::new(event.to,
                                                &self.network_config,
                                                event.receive_time(),
+                                               self.initial_time,
                                                &mut self.queue,
                                                &mut self.metrics);
                     if !recipient.run(event, env) {
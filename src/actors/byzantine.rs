@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use simulator::Environment;
+use simulator::Event as SimulatorEvent;
+use simulator::metrics::Metrics;
+use simulator::Node;
+use simulator::UniqueId;
+
+use crate::actors::honest::HonestActor;
+use crate::actors::send_to_validators;
+use crate::datastructures::block::Block;
+use crate::datastructures::block::MacroBlock;
+use crate::datastructures::hash::Hash;
+use crate::datastructures::pbft::PbftProof;
+use crate::datastructures::signature::KeyPair;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+use crate::simulation::scenario::PbftAttack;
+
+/// A validator that deviates from PBFT in the configured ways.
+/// Its own votes are sent directly to all other validators, such that they bypass Handel,
+/// and gossiped over the validator overlay to those it is not connected to.
+/// Micro blocks are produced and processed honestly.
+pub struct ByzantineActor {
+    actor: HonestActor,
+    key_pair: KeyPair,
+    attacks: Vec<PbftAttack>,
+    validator_ids: Vec<UniqueId>,
+    /// The macro block number of the proposals seen.
+    block_number: u32,
+    /// The proposals seen for this macro block and the view they were proposed in.
+    proposals: HashMap<Hash, u16>,
+}
+
+impl Node for ByzantineActor {
+    type EventType = Event;
+    type MetricsEventType = MetricsEventType;
+
+    fn run(&mut self, event: SimulatorEvent<Self::EventType>, mut env: Environment<Self::EventType, Self::MetricsEventType>) -> bool {
+        if let Event::BlockProduced(Block::Macro(ref proposal)) = *event.inner() {
+            env.note_event(&MetricsEventType::MessageEvent {
+                own: env.own_id(),
+                event: Box::new(event.inner().clone()),
+                from: event.from(),
+            }, event.receive_time());
+
+            self.propose(proposal, &mut env);
+            return true;
+        }
+
+        let proposal = match event.inner() {
+            Event::BlockProposal(proposal, _, _) => Some(proposal),
+            Event::ValidatorGossip(inner) => match **inner {
+                Event::BlockProposal(ref proposal, _, _) => Some(proposal),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some(proposal) = proposal {
+            self.observe_proposal(proposal, &mut env);
+
+            // Our own protocol would prepare the proposal.
+            if !self.votes_honestly() {
+                return true;
+            }
+        }
+
+        self.actor.run(event, env)
+    }
+}
+
+impl ByzantineActor {
+    pub fn new(actor: HonestActor, key_pair: KeyPair, attacks: Vec<PbftAttack>, validator_ids: Vec<UniqueId>) -> Self {
+        ByzantineActor {
+            actor,
+            key_pair,
+            attacks,
+            validator_ids,
+            block_number: 0,
+            proposals: HashMap::new(),
+        }
+    }
+
+    fn has_attack(&self, attack: PbftAttack) -> bool {
+        self.attacks.contains(&attack)
+    }
+
+    /// Whether our own protocol may vote for the proposals it receives.
+    fn votes_honestly(&self) -> bool {
+        !self.has_attack(PbftAttack::WithholdVotes) && !self.has_attack(PbftAttack::StaleVotes)
+    }
+
+    /// Sends our proposal to all validators, or two conflicting proposals to disjoint halves of them.
+    /// Halves are only disjoint if we are connected to all validators, otherwise both proposals are gossiped.
+    /// Our own protocol receives the first proposal like any other validator, unless we do not vote honestly.
+    fn propose(&mut self, proposal: &MacroBlock, env: &mut Environment<Event, MetricsEventType>) {
+        let signature = self.key_pair.secret_key().sign(&proposal.header);
+        let first = Event::BlockProposal(proposal.clone(), signature.clone(), None);
+
+        let second = if self.has_attack(PbftAttack::DoubleProposal) {
+            // A different timestamp suffices to obtain a different, but valid proposal.
+            let mut twin = proposal.clone();
+            twin.extrinsics.timestamp += 1;
+            twin.header.extrinsics_root = twin.extrinsics.hash();
+            let twin_signature = self.key_pair.secret_key().sign(&twin.header);
+            debug!("Proposing two macro blocks #{} in view {}", proposal.header.digest.block_number, proposal.header.digest.view_number);
+
+            self.observe_proposal(&twin, env);
+            Event::BlockProposal(twin, twin_signature, None)
+        } else {
+            first.clone()
+        };
+        self.observe_proposal(proposal, env);
+
+        let own_id = env.own_id();
+        let peers: Vec<UniqueId> = self.validator_ids.iter().cloned().filter(|&id| id != own_id).collect();
+        send_to_validators(&first, peers.iter().cloned().step_by(2), &self.validator_ids, env);
+        send_to_validators(&second, peers.iter().cloned().skip(1).step_by(2), &self.validator_ids, env);
+
+        if self.votes_honestly() {
+            env.schedule_self(first, env.time());
+        }
+    }
+
+    /// Remembers a proposal and sends the votes of our attacks.
+    fn observe_proposal(&mut self, proposal: &MacroBlock, env: &mut Environment<Event, MetricsEventType>) {
+        let block_number = proposal.header.digest.block_number;
+        if block_number != self.block_number {
+            self.block_number = block_number;
+            self.proposals.clear();
+        }
+
+        let hash = proposal.header.hash();
+        let view_number = proposal.header.digest.view_number;
+        if self.proposals.contains_key(&hash) {
+            return;
+        }
+
+        if self.has_attack(PbftAttack::StaleVotes) {
+            let stale: Vec<(Hash, u16)> = self.proposals.iter()
+                .filter(|(_, &view)| view < view_number)
+                .map(|(hash, &view)| (hash.clone(), view))
+                .collect();
+            for (hash, view) in stale {
+                self.vote(&hash, view, env);
+            }
+        }
+
+        if self.has_attack(PbftAttack::ConflictingVotes) {
+            self.vote(&hash, view_number, env);
+        }
+
+        self.proposals.insert(hash, view_number);
+    }
+
+    /// Sends a prepare in the given view and a commit for the proposal with the given hash to all validators.
+    fn vote(&self, hash: &Hash, view_number: u16, env: &mut Environment<Event, MetricsEventType>) {
+        let prepare = PbftProof::prepare(hash, view_number, &self.key_pair.secret_key());
+        let commit = PbftProof::commit(hash, &self.key_pair.secret_key());
+
+        let own_id = env.own_id();
+        let peers = self.validator_ids.iter().cloned().filter(|&id| id != own_id);
+        send_to_validators(&Event::BlockPrepare(prepare), peers.clone(), &self.validator_ids, env);
+        send_to_validators(&Event::BlockCommit(commit), peers, &self.validator_ids, env);
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use simulator::Environment;
+use simulator::Event as SimulatorEvent;
+use simulator::metrics::Metrics;
+use simulator::Node;
+use simulator::UniqueId;
+
+use crate::actors::honest::HonestActor;
+use crate::actors::send_to_validators;
+use crate::datastructures::block::Block;
+use crate::datastructures::block::MacroBlock;
+use crate::datastructures::block::MicroBlock;
+use crate::datastructures::hash::Hash;
+use crate::datastructures::pbft::PbftProof;
+use crate::datastructures::pbft::ViewChange;
+use crate::datastructures::signature::KeyPair;
+use crate::datastructures::signature::PublicKey;
+use crate::protocol::producer_index;
+use crate::protocol::ProtocolConfig;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+use crate::simulation::scenario::Release;
+use crate::simulation::scenario::ScriptedStrategy;
+use crate::simulation::scenario::Strategy;
+
+/// The adversary controlling all corrupted validators.
+/// It holds their keys and everything they learn, and it follows the scenario's script.
+/// Besides the statically corrupted validators, it may adaptively corrupt upcoming producers,
+/// which it predicts from the public blocks and view changes, for as long as its budget lasts.
+/// Such a validator stays corrupted until it receives a block at the height of its slot.
+/// Nodes outside the adversary are split into two fixed halves,
+/// such that all corrupted nodes send conflicting messages to the same halves.
+/// Honest validators are split evenly, other nodes by the parity of their id.
+pub struct Adversary {
+    script: Vec<ScriptedStrategy>,
+    protocol_config: ProtocolConfig,
+    /// The validators in the order of the genesis block.
+    validators: Vec<PublicKey>,
+    /// The validators corrupted from the start.
+    corrupted: HashSet<UniqueId>,
+    /// The adaptively corrupted validators and the block number of their slot.
+    victims: HashMap<UniqueId, u32>,
+    /// The number of slots that can still be corrupted adaptively.
+    budget: usize,
+    /// The last block seen by any corrupted node at each block number since the last macro block.
+    /// Used to derive the producer schedule.
+    blocks: HashMap<u32, Block>,
+    /// The honest validators in the second half.
+    second_half: HashSet<UniqueId>,
+    /// The proposals all corrupted validators voted for.
+    voted: HashSet<Hash>,
+}
+
+impl Adversary {
+    pub fn new(script: Vec<ScriptedStrategy>, protocol_config: ProtocolConfig,
+               genesis_block: &MacroBlock, corrupted: &[UniqueId], budget: usize) -> Self {
+        let validators = genesis_block.header.digest.validators.clone();
+        let mut honest: Vec<UniqueId> = validators.iter()
+            .map(|key| key.id() as UniqueId)
+            .filter(|id| !corrupted.contains(id))
+            .collect();
+        honest.sort_unstable();
+        let second_half = honest.into_iter().skip(1).step_by(2).collect();
+
+        Adversary {
+            script,
+            protocol_config,
+            validators,
+            corrupted: corrupted.iter().cloned().collect(),
+            victims: HashMap::new(),
+            budget,
+            blocks: vec![(0, Block::Macro(genesis_block.clone()))].into_iter().collect(),
+            second_half,
+            voted: HashSet::new(),
+        }
+    }
+
+    /// Returns the strategies active at `elapsed` since the start of the simulation in the order of the script.
+    fn active(&self, elapsed: Duration) -> impl Iterator<Item=&Strategy> {
+        self.script.iter()
+            .filter(move |scripted| scripted.is_active(elapsed))
+            .map(|scripted| &scripted.strategy)
+    }
+
+    fn is_corrupted(&self, id: UniqueId) -> bool {
+        self.corrupted.contains(&id) || self.victims.contains_key(&id)
+    }
+
+    /// The key pairs of all currently corrupted validators.
+    fn key_pairs(&self) -> Vec<KeyPair> {
+        self.corrupted.iter()
+            .chain(self.victims.keys())
+            .map(|&id| KeyPair::from_id(id as u64))
+            .collect()
+    }
+
+    /// The ids of all validators.
+    fn validator_ids(&self) -> Vec<UniqueId> {
+        self.validators.iter().map(|key| key.id() as UniqueId).collect()
+    }
+
+    /// Whether a corrupted validator is offline.
+    fn is_crashed(&self, elapsed: Duration) -> bool {
+        self.active(elapsed).any(|strategy| matches!(strategy, Strategy::Crash))
+    }
+
+    /// Whether a node receives the first of two conflicting messages.
+    fn in_first_half(&self, id: UniqueId) -> bool {
+        if self.validators.iter().any(|key| key.id() as UniqueId == id) {
+            return !self.second_half.contains(&id);
+        }
+        id.is_multiple_of(2)
+    }
+
+    /// Whether the producer of the given block in the given view is corrupted,
+    /// as far as the previous block is known.
+    fn is_corrupted_slot(&self, block_number: u32, view_number: u16) -> bool {
+        self.blocks.get(&(block_number - 1))
+            .map(|previous| {
+                let producer = &self.validators[producer_index(previous, view_number, self.validators.len())];
+                self.is_corrupted(producer.id() as UniqueId)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Learns about a public block and corrupts the producer of the next block, if it is a new height.
+    fn observe_block(&mut self, block: &Block, env: &mut Environment<Event, MetricsEventType>) {
+        let block_number = block.block_number();
+        let hash = block.hash();
+        if self.blocks.get(&block_number).is_some_and(|known| known.hash() == hash) {
+            return;
+        }
+
+        let new_height = self.blocks.keys().all(|&number| number <= block_number);
+        if let Block::Macro(_) = block {
+            self.blocks.retain(|&number, _| number >= block_number);
+        }
+        self.blocks.insert(block_number, block.clone());
+
+        if new_height {
+            self.corrupt_producer(block_number + 1, 0, env);
+        }
+    }
+
+    /// Releases an adaptively corrupted validator once it receives a block at the height of its slot.
+    fn release(&mut self, id: UniqueId, block_number: u32) {
+        if self.victims.get(&id).is_some_and(|&slot| slot <= block_number) {
+            self.victims.remove(&id);
+        }
+    }
+
+    /// Learns about a view change and corrupts the producer of the new view.
+    fn observe_view_change(&mut self, view_change: &ViewChange, env: &mut Environment<Event, MetricsEventType>) {
+        self.corrupt_producer(view_change.internals.block_number, view_change.internals.new_view_number, env);
+    }
+
+    /// Corrupts the producer of the given block in the given view,
+    /// if the budget allows and no more than f validators would be corrupted at the same time.
+    fn corrupt_producer(&mut self, block_number: u32, view_number: u16, env: &mut Environment<Event, MetricsEventType>) {
+        let max_corrupted = usize::from(self.protocol_config.max_malicious());
+        if self.budget == 0 || self.corrupted.len() + self.victims.len() >= max_corrupted {
+            return;
+        }
+
+        let producer = match self.blocks.get(&(block_number - 1)) {
+            Some(previous) => self.validators[producer_index(previous, view_number, self.validators.len())].id() as UniqueId,
+            None => return,
+        };
+        if self.is_corrupted(producer) {
+            return;
+        }
+
+        self.budget -= 1;
+        self.victims.insert(producer, block_number);
+        env.note_event(&MetricsEventType::Corrupted {
+            own: producer,
+            block_number,
+            view_number,
+        }, env.time());
+    }
+
+    /// Handles a micro block produced by a corrupted validator and returns whether it was handled.
+    fn produced_micro_block(&mut self, block: &MicroBlock, elapsed: Duration, env: &mut Environment<Event, MetricsEventType>) -> bool {
+        let strategy = self.active(elapsed)
+            .find(|strategy| matches!(strategy, Strategy::Equivocate | Strategy::Withhold(_)))
+            .cloned();
+
+        match strategy {
+            Some(Strategy::Equivocate) => self.equivocate(block, env),
+            Some(Strategy::Withhold(release)) => self.withhold(block, &release, env),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Sends the block to the first half of the peers and a conflicting block to the second half.
+    /// The producer's protocol learns about the blocks only from its peers.
+    fn equivocate(&self, block: &MicroBlock, env: &mut Environment<Event, MetricsEventType>) {
+        let key_pair = KeyPair::from_id(env.own_id() as u64);
+        let mut twin = block.clone();
+        twin.extrinsics.timestamp += 1;
+        twin.header.extrinsics_root = twin.extrinsics.hash();
+        twin.justification = key_pair.secret_key().sign(&twin.header);
+
+        let block = Block::Micro(block.clone());
+        let twin = Block::Micro(twin);
+        env.note_event(&MetricsEventType::Equivocation {
+            own: env.own_id(),
+            block: Box::new(twin.clone()),
+        }, env.time());
+
+        let peers = env.peers().into_owned();
+        for peer in peers {
+            let event = if self.in_first_half(peer) { block.clone() } else { twin.clone() };
+            env.send_to(peer, Event::Block(event));
+        }
+    }
+
+    /// Releases the block to the peers and the producer's protocol after the delay.
+    fn withhold(&self, block: &MicroBlock, release: &Release, env: &mut Environment<Event, MetricsEventType>) {
+        let block = Block::Micro(block.clone());
+        let delay = release.delay(&self.protocol_config, block.view_number());
+        env.note_event(&MetricsEventType::Withheld {
+            own: env.own_id(),
+            hash: block.hash(),
+            delay,
+        }, env.time());
+
+        let release_time = env.time() + delay;
+        env.scheduled_broadcast(Event::Block(block.clone()), release_time);
+        env.schedule_self(Event::Block(block), release_time);
+    }
+
+    /// Whether a corrupted validator ignores its micro block timeout,
+    /// because it would replace a corrupted producer while withholding.
+    fn suppresses_timeout(&self, block_number: u32, view_number: u16, elapsed: Duration) -> bool {
+        self.active(elapsed).any(|strategy| matches!(strategy, Strategy::Withhold(_)))
+            && self.is_corrupted_slot(block_number, view_number)
+    }
+
+    /// Handles a proposal produced by a corrupted leader and returns whether it was handled.
+    /// When splitting votes, the leader sends two conflicting proposals to the halves of the validators.
+    /// Validators the leader is not connected to are reached over gossip, which delivers both proposals to everyone.
+    fn produced_proposal(&mut self, proposal: &MacroBlock, elapsed: Duration, env: &mut Environment<Event, MetricsEventType>) -> bool {
+        if !self.active(elapsed).any(|strategy| matches!(strategy, Strategy::SplitVotes)) {
+            return false;
+        }
+
+        let key_pair = KeyPair::from_id(env.own_id() as u64);
+        let signature = key_pair.secret_key().sign(&proposal.header);
+        let mut twin = proposal.clone();
+        twin.extrinsics.timestamp += 1;
+        twin.header.extrinsics_root = twin.extrinsics.hash();
+        let twin_signature = key_pair.secret_key().sign(&twin.header);
+        debug!("Adversary proposes two macro blocks #{} in view {}", proposal.header.digest.block_number, proposal.header.digest.view_number);
+
+        self.observe_proposal(proposal, elapsed, env);
+        self.observe_proposal(&twin, elapsed, env);
+
+        let first = Event::BlockProposal(proposal.clone(), signature, None);
+        let second = Event::BlockProposal(twin, twin_signature, None);
+        let own_id = env.own_id();
+        let validator_ids = self.validator_ids();
+        let (first_half, second_half): (Vec<UniqueId>, Vec<UniqueId>) = validator_ids.iter()
+            .filter(|&&id| id != own_id)
+            .partition(|&&id| self.in_first_half(id));
+        send_to_validators(&first, first_half, &validator_ids, env);
+        send_to_validators(&second, second_half, &validator_ids, env);
+
+        // The leader's protocol processes the first proposal like any other.
+        env.schedule_self(first, env.time());
+        true
+    }
+
+    /// When splitting votes, sends a prepare and a commit of every corrupted validator for every new proposal.
+    fn observe_proposal(&mut self, proposal: &MacroBlock, elapsed: Duration, env: &mut Environment<Event, MetricsEventType>) {
+        if !self.active(elapsed).any(|strategy| matches!(strategy, Strategy::SplitVotes)) {
+            return;
+        }
+
+        let hash = proposal.header.hash();
+        if !self.voted.insert(hash.clone()) {
+            return;
+        }
+
+        let own_id = env.own_id();
+        let validator_ids = self.validator_ids();
+        let peers = validator_ids.iter().cloned().filter(|&id| id != own_id);
+        for key_pair in self.key_pairs() {
+            let prepare = PbftProof::prepare(&hash, proposal.header.digest.view_number, &key_pair.secret_key());
+            let commit = PbftProof::commit(&hash, &key_pair.secret_key());
+            send_to_validators(&Event::BlockPrepare(prepare), peers.clone(), &validator_ids, env);
+            send_to_validators(&Event::BlockCommit(commit), peers.clone(), &validator_ids, env);
+        }
+    }
+}
+
+/// A validator corrupted by the adversary or watched by it to be corrupted adaptively.
+/// While corrupted, events are passed to the adversary first and only reach the honest protocol if the adversary does not handle them.
+pub struct CoordinatedActor {
+    actor: HonestActor,
+    adversary: Arc<Mutex<Adversary>>,
+}
+
+impl Node for CoordinatedActor {
+    type EventType = Event;
+    type MetricsEventType = MetricsEventType;
+
+    fn run(&mut self, event: SimulatorEvent<Self::EventType>, mut env: Environment<Self::EventType, Self::MetricsEventType>) -> bool {
+        let elapsed = env.time() - env.initial_time();
+        let mut adversary = self.adversary.lock().unwrap();
+
+        if let Event::Block(ref block) = *event.inner() {
+            adversary.release(env.own_id(), block.block_number());
+        }
+
+        let corrupted = adversary.is_corrupted(env.own_id());
+        let crashed = corrupted && adversary.is_crashed(elapsed);
+        let handled = crashed || (corrupted && match *event.inner() {
+            Event::BlockProduced(Block::Micro(ref block)) => adversary.produced_micro_block(block, elapsed, &mut env),
+            Event::BlockProduced(Block::Macro(ref proposal)) => adversary.produced_proposal(proposal, elapsed, &mut env),
+            Event::BlockProposal(ref proposal, _, _) => {
+                adversary.observe_proposal(proposal, elapsed, &mut env);
+                false
+            },
+            Event::ValidatorGossip(ref inner) => {
+                if let Event::BlockProposal(ref proposal, _, _) = **inner {
+                    adversary.observe_proposal(proposal, elapsed, &mut env);
+                }
+                false
+            },
+            Event::MicroBlockTimeout(block_number, view_number) => adversary.suppresses_timeout(block_number, view_number, elapsed),
+            _ => false,
+        });
+
+        // Blocks and view changes are public, the adversary learns them from every node it watches.
+        // Blocks produced by a corrupted validator become public only when it sends them.
+        match *event.inner() {
+            Event::Block(ref block) => adversary.observe_block(block, &mut env),
+            Event::BlockProduced(ref block) if !handled => adversary.observe_block(block, &mut env),
+            Event::ViewChange(ref view_change) => adversary.observe_view_change(view_change, &mut env),
+            Event::ValidatorGossip(ref inner) => {
+                if let Event::ViewChange(ref view_change) = **inner {
+                    adversary.observe_view_change(view_change, &mut env);
+                }
+            },
+            _ => {},
+        }
+        drop(adversary);
+
+        if handled {
+            if let (Event::BlockProduced(_), false) = (event.inner(), crashed) {
+                env.note_event(&MetricsEventType::MessageEvent {
+                    own: env.own_id(),
+                    event: Box::new(event.inner().clone()),
+                    from: event.from(),
+                }, event.receive_time());
+            }
+            return true;
+        }
+
+        self.actor.run(event, env)
+    }
+}
+
+impl CoordinatedActor {
+    pub fn new(actor: HonestActor, adversary: Arc<Mutex<Adversary>>) -> Self {
+        CoordinatedActor {
+            actor,
+            adversary,
+        }
+    }
+}
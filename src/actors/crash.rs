@@ -0,0 +1,39 @@
+use simulator::Environment;
+use simulator::Event as SimulatorEvent;
+use simulator::Node;
+
+use crate::actors::honest::HonestActor;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+use crate::simulation::scenario::CrashSchedule;
+
+/// A validator that follows the protocol honestly until it crashes.
+/// While offline, it neither receives nor sends anything and its own timeouts are lost.
+/// After recovering, it catches up through the regular synchronisation once it receives a block.
+pub struct CrashActor {
+    actor: HonestActor,
+    schedule: CrashSchedule,
+}
+
+impl Node for CrashActor {
+    type EventType = Event;
+    type MetricsEventType = MetricsEventType;
+
+    fn run(&mut self, event: SimulatorEvent<Self::EventType>, env: Environment<Self::EventType, Self::MetricsEventType>) -> bool {
+        if self.schedule.is_down(env.time() - env.initial_time()) {
+            // The simulation is ended by the nodes that are online.
+            return true;
+        }
+
+        self.actor.run(event, env)
+    }
+}
+
+impl CrashActor {
+    pub fn new(actor: HonestActor, schedule: CrashSchedule) -> Self {
+        CrashActor {
+            actor,
+            schedule,
+        }
+    }
+}
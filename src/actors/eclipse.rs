@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+
+use simulator::Environment;
+use simulator::Event as SimulatorEvent;
+use simulator::metrics::Metrics;
+use simulator::Node;
+use simulator::UniqueId;
+
+use crate::datastructures::hash::Hash;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+use crate::simulation::scenario::EclipseFilter;
+
+/// An attacker node that fills connection slots of the victims of an eclipse.
+/// It does not run the protocol, but passes on messages from its other peers to its victims as far as the filter allows.
+/// Messages from the victims are relayed faithfully, such that the victims do not notice the attack.
+pub struct EclipseActor {
+    victims: HashSet<UniqueId>,
+    filter: EclipseFilter,
+    /// Blocks arrive from several peers, but are only passed on once.
+    blocks: HashSet<Hash>,
+}
+
+impl Node for EclipseActor {
+    type EventType = Event;
+    type MetricsEventType = MetricsEventType;
+
+    fn run(&mut self, event: SimulatorEvent<Self::EventType>, mut env: Environment<Self::EventType, Self::MetricsEventType>) -> bool {
+        let from = event.from();
+        let event = event.inner();
+        let peers: Vec<UniqueId> = env.peers().iter().cloned().collect();
+
+        if self.victims.contains(&from) {
+            for peer in peers.into_iter().filter(|peer| !self.victims.contains(peer)) {
+                env.send_to(peer, event.clone());
+            }
+            return true;
+        }
+
+        if let Event::Block(ref block) = *event {
+            if !self.blocks.insert(block.hash()) {
+                return true;
+            }
+        }
+
+        let delay = self.filter.delay(event);
+        for victim in peers.into_iter().filter(|peer| self.victims.contains(peer)) {
+            match delay {
+                Some(delay) => {
+                    env.schedule(victim, event.clone(), env.time() + delay);
+                },
+                None => env.note_event(&MetricsEventType::Eclipsed {
+                    own: env.own_id(),
+                    victim,
+                    message_type: event.message_type(),
+                }, env.time()),
+            }
+        }
+        true
+    }
+}
+
+impl EclipseActor {
+    pub fn new(victims: HashSet<UniqueId>, filter: EclipseFilter) -> Self {
+        EclipseActor {
+            victims,
+            filter,
+            blocks: HashSet::new(),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use simulator::Environment;
+use simulator::Event as SimulatorEvent;
+use simulator::metrics::Metrics;
+use simulator::Node;
+
+use crate::actors::honest::HonestActor;
+use crate::datastructures::block::Block;
+use crate::datastructures::block::MicroBlock;
+use crate::datastructures::signature::KeyPair;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+
+/// A validator that signs two different micro blocks whenever it is the producer
+/// and sends them to disjoint halves of its peers.
+/// Otherwise, it follows the protocol honestly and adopts whichever of its blocks reaches it first.
+pub struct EquivocatingActor {
+    actor: HonestActor,
+    key_pair: KeyPair,
+}
+
+impl Node for EquivocatingActor {
+    type EventType = Event;
+    type MetricsEventType = MetricsEventType;
+
+    fn run(&mut self, event: SimulatorEvent<Self::EventType>, mut env: Environment<Self::EventType, Self::MetricsEventType>) -> bool {
+        if let Event::BlockProduced(Block::Micro(ref block)) = *event.inner() {
+            env.note_event(&MetricsEventType::MessageEvent {
+                own: env.own_id(),
+                event: Box::new(event.inner().clone()),
+                from: event.from(),
+            }, event.receive_time());
+
+            self.equivocate(block, &mut env);
+            return true;
+        }
+
+        self.actor.run(event, env)
+    }
+}
+
+impl EquivocatingActor {
+    pub fn new(actor: HonestActor, key_pair: KeyPair) -> Self {
+        EquivocatingActor {
+            actor,
+            key_pair,
+        }
+    }
+
+    /// Sends the produced block to every other peer and a conflicting block to the remaining ones.
+    /// Our own protocol learns about the blocks only from our peers.
+    fn equivocate(&self, block: &MicroBlock, env: &mut Environment<Event, MetricsEventType>) {
+        // A different timestamp suffices to obtain a different, but valid block.
+        let mut twin = block.clone();
+        twin.extrinsics.timestamp += 1;
+        twin.header.extrinsics_root = twin.extrinsics.hash();
+        twin.justification = self.key_pair.secret_key().sign(&twin.header);
+
+        let block = Block::Micro(block.clone());
+        let twin = Block::Micro(twin);
+        env.note_event(&MetricsEventType::Equivocation {
+            own: env.own_id(),
+            block: Box::new(twin.clone()),
+        }, env.time());
+
+        let peers = env.peers().into_owned();
+        for (i, peer) in peers.into_iter().enumerate() {
+            let event = if i % 2 == 0 { block.clone() } else { twin.clone() };
+            env.send_to(peer, Event::Block(event));
+        }
+    }
+}
@@ -2,18 +2,19 @@ use simulator::Environment;
 use simulator::Event as SimulatorEvent;
 use simulator::metrics::Metrics;
 use simulator::Node;
+use simulator::UniqueId;
 
 use crate::actors::Timing;
 use crate::datastructures::block::MacroBlock;
 use crate::datastructures::signature::KeyPair;
-use crate::protocol::honest_protocol::HonestProtocol;
+use crate::protocol::Protocol;
 use crate::protocol::ProtocolConfig;
 use crate::simulation::Event;
 use crate::simulation::metrics::MetricsEventType;
 use crate::simulation::SimulationConfig;
 
 pub struct HonestActor {
-    protocol: HonestProtocol,
+    protocol: Box<dyn Protocol>,
     simulation_config: SimulationConfig,
 }
 
@@ -24,30 +25,11 @@ impl Node for HonestActor {
     fn run(&mut self, event: SimulatorEvent<Self::EventType>, mut env: Environment<Self::EventType, Self::MetricsEventType>) -> bool {
         env.note_event(&MetricsEventType::MessageEvent {
             own: env.own_id(),
-            event: event.inner().clone(),
+            event: Box::new(event.inner().clone()),
             from: event.from(),
         }, event.receive_time());
 
-        match event.inner() {
-            // External events.
-            Event::Block(block) => self.protocol.received_block(block.clone(), &mut env),
-            Event::Transaction(_transaction) => (),
-
-            // PBFT.
-            Event::ViewChange(view_change) => self.protocol.handle_view_change(view_change.clone(), &mut env),
-            Event::BlockProposal(proposal, signature) => self.protocol.handle_macro_block_proposal(proposal.clone(), signature.clone(), &mut env),
-            Event::BlockPrepare(proof) => self.protocol.handle_prepare(proof.clone(), &mut env),
-            Event::BlockCommit(proof) => self.protocol.handle_commit(proof.clone(), &mut env),
-
-            // Internal events.
-            Event::BlockProcessed(block) => self.protocol.processed_block(block.clone(), &mut env),
-            Event::BlockProduced(block) => self.protocol.produced_block(block.clone(), &mut env),
-            Event::ProposalProcessed(block, signature) => self.protocol.processed_proposal(block.clone(), signature.clone(), &mut env),
-            Event::TransactionProcessed(_transaction) => (),
-            Event::MicroBlockTimeout(block_number, view_number) | Event::MacroBlockTimeout(block_number, view_number, _) => self.protocol.handle_timeout(*block_number, *view_number, &mut env),
-
-            Event::Init => self.protocol.prepare_next_block(&mut env),
-        }
+        self.handle_event(event.inner(), event.from(), &mut env);
 
         // Run for the configured amount of blocks.
         self.protocol.current_block_number() < self.simulation_config.blocks
@@ -57,11 +39,53 @@ impl Node for HonestActor {
 impl HonestActor {
     pub fn new(simulation_config: SimulationConfig,
                protocol_config: ProtocolConfig, timing: Timing,
-               genesis_block: MacroBlock, key_pair: KeyPair) -> Self {
+               genesis_block: MacroBlock, key_pair: KeyPair, clock_skew: i64) -> Self {
         HonestActor {
-            protocol: HonestProtocol::new(protocol_config, timing,
-                                          genesis_block, key_pair),
+            protocol: protocol_config.variant.create(protocol_config, timing,
+                                                     genesis_block, key_pair, clock_skew),
             simulation_config,
         }
     }
+
+    /// Dispatches an event to the corresponding protocol handler.
+    fn handle_event(&mut self, event: &Event, from: UniqueId, env: &mut Environment<Event, MetricsEventType>) {
+        match event {
+            // External events.
+            Event::Block(block) => self.protocol.received_block(block.clone(), from, env),
+            Event::Transaction(_transaction) => (),
+
+            // Synchronisation.
+            Event::BlockRequest(request) => self.protocol.handle_block_request(request, from, env),
+            Event::BlockResponse(request, blocks) => self.protocol.handle_block_response(request, blocks.clone(), env),
+            Event::MacroHeaders(_headers) => (),
+
+            // PBFT.
+            Event::ViewChange(view_change) => self.protocol.handle_view_change(view_change.clone(), env),
+            Event::BlockProposal(proposal, signature, certificate) => self.protocol.handle_macro_block_proposal(proposal.clone(), signature.clone(), certificate.clone(), env),
+            Event::BlockPrepare(proof) => self.protocol.handle_prepare(proof.clone(), env),
+            Event::BlockCommit(proof) => self.protocol.handle_commit(proof.clone(), env),
+            Event::HandelContribution(contribution) => self.protocol.handle_contribution(contribution.clone(), env),
+            Event::ValidatorGossip(inner) => {
+                if self.protocol.relay_validator_gossip(inner, from, env) {
+                    self.handle_event(inner, from, env);
+                }
+            },
+
+            // Internal events.
+            Event::BlockProcessed(block) => self.protocol.processed_block(block.clone(), env),
+            Event::BlockProduced(block) => self.protocol.produced_block(block.clone(), env),
+            Event::ProposalProcessed(block, signature, certificate) => self.protocol.processed_proposal(block.clone(), signature.clone(), certificate.clone(), env),
+            Event::MacroHeaderProcessed(_header) => (),
+            Event::HandelContributionProcessed(contribution) => self.protocol.processed_contribution(contribution.clone(), env),
+            Event::HandelTimeout(message) => self.protocol.handle_handel_timeout(message.clone(), env),
+            Event::TransactionProcessed(_transaction) => (),
+            Event::BlockProductionDue(block_number, view_number) => self.protocol.handle_production_due(*block_number, *view_number, env),
+            Event::MicroBlockTimeout(block_number, view_number) => self.protocol.handle_timeout(*block_number, *view_number, env),
+            Event::BlockRequestTimeout(id) => self.protocol.handle_request_timeout(*id, env),
+            Event::MacroBlockTimeout(block_number, view_number, phase) => self.protocol.handle_macro_block_timeout(*block_number, *view_number, *phase, env),
+            Event::ViewChangeTimeout(block_number, view_number) => self.protocol.handle_view_change_timeout(*block_number, *view_number, env),
+
+            Event::Init => self.protocol.init(env),
+        }
+    }
 }
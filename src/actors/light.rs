@@ -0,0 +1,57 @@
+use simulator::Environment;
+use simulator::Event as SimulatorEvent;
+use simulator::metrics::Metrics;
+use simulator::Node;
+
+use crate::actors::Timing;
+use crate::datastructures::block::MacroBlock;
+use crate::protocol::light_protocol::LightProtocol;
+use crate::protocol::ProtocolConfig;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+
+pub struct LightActor {
+    protocol: LightProtocol,
+}
+
+impl Node for LightActor {
+    type EventType = Event;
+    type MetricsEventType = MetricsEventType;
+
+    fn run(&mut self, event: SimulatorEvent<Self::EventType>, mut env: Environment<Self::EventType, Self::MetricsEventType>) -> bool {
+        env.note_event(&MetricsEventType::MessageEvent {
+            own: env.own_id(),
+            event: Box::new(event.inner().clone()),
+            from: event.from(),
+        }, event.receive_time());
+
+        self.handle_event(event.inner(), &mut env);
+
+        // The simulation is ended by the full nodes.
+        true
+    }
+}
+
+impl LightActor {
+    pub fn new(protocol_config: ProtocolConfig, timing: Timing, genesis_block: MacroBlock) -> Self {
+        LightActor {
+            protocol: LightProtocol::new(protocol_config, timing, genesis_block),
+        }
+    }
+
+    /// Dispatches an event to the corresponding protocol handler.
+    fn handle_event(&mut self, event: &Event, env: &mut Environment<Event, MetricsEventType>) {
+        match event {
+            // Synchronisation.
+            Event::MacroHeaders(headers) => self.protocol.received_headers(headers.clone(), env),
+
+            // Internal events.
+            Event::MacroHeaderProcessed(header) => self.protocol.processed_header(header.clone(), env),
+
+            Event::Init => self.protocol.request_headers(env),
+
+            // Light nodes neither follow micro blocks nor take part in consensus.
+            _ => (),
+        }
+    }
+}
@@ -1,11 +1,27 @@
 use std::time::Duration;
 
+use simulator::Environment;
+use simulator::UniqueId;
+
 use crate::datastructures::block::Block;
-use crate::datastructures::block::BlockType;
 use crate::datastructures::block::MacroBlock;
-use crate::simulation::settings::TimingSettings;
+use crate::datastructures::block::MacroHeader;
+use crate::datastructures::pbft::PbftJustification;
+use crate::datastructures::pbft::PrepareCertificate;
+use crate::datastructures::signature::Signature;
+use crate::datastructures::sync::FinalizedHeader;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+use crate::simulation::settings::SignatureTimingSettings;
 
+pub mod byzantine;
+pub mod coordinated;
+pub mod crash;
+pub mod eclipse;
+pub mod equivocating;
 pub mod honest;
+pub mod light;
+pub mod withholding;
 
 #[derive(Clone, Debug)]
 pub struct Timing {
@@ -23,32 +39,87 @@ pub trait VerificationTime {
     fn verification_time(&self, timing: &Timing) -> Duration;
 }
 
+/// The time it takes to create the signatures and aggregates of a datastructure.
+pub trait GenerationTime {
+    fn generation_time(&self, timing: &Timing) -> Duration;
+}
+
 impl Timing {
-    pub(crate) fn from_settings(timing: TimingSettings) -> Self {
+    pub(crate) fn from_settings(timing: &SignatureTimingSettings) -> Self {
+        Timing {
+            signing: Duration::from_micros(timing.signing),
+            verification: Duration::from_micros(timing.verification),
+            batch_verification: Duration::from_micros(timing.batch_verification),
+            generate_aggregate_signature_same_message: Duration::from_micros(timing.generate_aggregate_signature_same_message),
+            generate_aggregate_public_key: Duration::from_micros(timing.generate_aggregate_public_key),
+            verify_aggregate_signature_same_message: Duration::from_micros(timing.verify_aggregate_signature_same_message),
+            generate_aggregate_signature_distinct_message: Duration::from_micros(timing.generate_aggregate_signature_distinct_message),
+            verify_aggregate_signature_distinct_message: Duration::from_micros(timing.verify_aggregate_signature_distinct_message),
+        }
+    }
+
+    /// Returns the timing on hardware on which all signature operations take `factor` times as long.
+    pub fn scaled(&self, factor: f64) -> Self {
         Timing {
-            signing: Duration::from_micros(timing.signatures.signing),
-            verification: Duration::from_micros(timing.signatures.verification),
-            batch_verification: Duration::from_micros(timing.signatures.batch_verification),
-            generate_aggregate_signature_same_message: Duration::from_micros(timing.signatures.generate_aggregate_signature_same_message),
-            generate_aggregate_public_key: Duration::from_micros(timing.signatures.generate_aggregate_public_key),
-            verify_aggregate_signature_same_message: Duration::from_micros(timing.signatures.verify_aggregate_signature_same_message),
-            generate_aggregate_signature_distinct_message: Duration::from_micros(timing.signatures.generate_aggregate_signature_distinct_message),
-            verify_aggregate_signature_distinct_message: Duration::from_micros(timing.signatures.verify_aggregate_signature_distinct_message),
+            signing: self.signing.mul_f64(factor),
+            verification: self.verification.mul_f64(factor),
+            batch_verification: self.batch_verification.mul_f64(factor),
+            generate_aggregate_signature_same_message: self.generate_aggregate_signature_same_message.mul_f64(factor),
+            generate_aggregate_public_key: self.generate_aggregate_public_key.mul_f64(factor),
+            verify_aggregate_signature_same_message: self.verify_aggregate_signature_same_message.mul_f64(factor),
+            generate_aggregate_signature_distinct_message: self.generate_aggregate_signature_distinct_message.mul_f64(factor),
+            verify_aggregate_signature_distinct_message: self.verify_aggregate_signature_distinct_message.mul_f64(factor),
         }
     }
 
     pub fn block_processing_time(&self, block: &Block) -> Duration {
-        match block.block_type() {
-            BlockType::Macro => Duration::from_millis(200),
-            BlockType::Micro => Duration::from_millis(10),
+        block.verification_time(self)
+    }
+
+    pub fn proposal_processing_time(&self, proposal: &MacroBlock, signature: &Signature<MacroHeader>,
+                                    certificate: Option<&PrepareCertificate>) -> Duration {
+        proposal.verification_time(self)
+            + signature.verification_time(self)
+            + certificate.map(|certificate| certificate.verification_time(self)).unwrap_or_default()
+    }
+
+    pub fn header_processing_time(&self, header: &FinalizedHeader) -> Duration {
+        header.justification.verification_time(self)
+    }
+
+    pub fn block_production_time(&self, block: &Block) -> Duration {
+        match block {
+            Block::Micro(_) => block.generation_time(self),
+            // Macro blocks are signed as proposals after production.
+            Block::Macro(_) => block.generation_time(self) + self.signing,
         }
     }
 
-    pub fn proposal_processing_time(&self, _block: &MacroBlock) -> Duration {
-        Duration::from_millis(10)
+    /// The time it takes to aggregate the votes of a committed proposal into its justification.
+    pub fn justification_time(&self, justification: &PbftJustification) -> Duration {
+        justification.generation_time(self)
+    }
+}
+
+/// Sends an event directly to the given validators.
+/// Validators we are not connected to are reached by gossiping the event over the validator overlay,
+/// in which case it reaches all validators.
+pub fn send_to_validators<I>(event: &Event, targets: I, validator_ids: &[UniqueId], env: &mut Environment<Event, MetricsEventType>)
+    where I: IntoIterator<Item=UniqueId> {
+    let num_unreachable = targets.into_iter()
+        .filter(|&id| !env.send_to(id, event.clone()))
+        .count();
+    if num_unreachable == 0 {
+        return;
     }
 
-    pub fn block_production_time(&self, _block: &Block) -> Duration {
-        Duration::from_millis(10)
+    debug!("Gossiping {} to reach {} validators we are not connected to", event.message_type(), num_unreachable);
+    let validator_peers: Vec<UniqueId> = env.peers().iter()
+        .filter(|peer| validator_ids.contains(peer))
+        .cloned()
+        .collect();
+    let gossip = Event::ValidatorGossip(Box::new(event.clone()));
+    for peer in validator_peers {
+        env.send_to(peer, gossip.clone());
     }
 }
@@ -0,0 +1,80 @@
+use simulator::Environment;
+use simulator::Event as SimulatorEvent;
+use simulator::metrics::Metrics;
+use simulator::Node;
+
+use crate::actors::honest::HonestActor;
+use crate::datastructures::block::Block;
+use crate::protocol::ProtocolConfig;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+use crate::simulation::scenario::Release;
+
+/// A validator that produces valid micro blocks, but withholds them before releasing them to its peers.
+/// It does not vote for a view change on its own withheld block.
+/// Otherwise, it follows the protocol honestly.
+pub struct WithholdingActor {
+    actor: HonestActor,
+    protocol_config: ProtocolConfig,
+    release: Release,
+    /// The block number and view of the block that is currently withheld.
+    withheld: Option<(u32, u16)>,
+}
+
+impl Node for WithholdingActor {
+    type EventType = Event;
+    type MetricsEventType = MetricsEventType;
+
+    fn run(&mut self, event: SimulatorEvent<Self::EventType>, mut env: Environment<Self::EventType, Self::MetricsEventType>) -> bool {
+        match *event.inner() {
+            Event::BlockProduced(ref block @ Block::Micro(_)) => {
+                env.note_event(&MetricsEventType::MessageEvent {
+                    own: env.own_id(),
+                    event: Box::new(event.inner().clone()),
+                    from: event.from(),
+                }, event.receive_time());
+
+                self.withhold(block, &mut env);
+                return true;
+            },
+            // Our own block is released now.
+            Event::Block(ref block) if event.from() == env.own_id()
+                && self.withheld == Some((block.block_number(), block.view_number())) => {
+                self.withheld = None;
+            },
+            Event::MicroBlockTimeout(block_number, view_number) if self.withheld == Some((block_number, view_number)) => {
+                return true;
+            },
+            _ => {},
+        }
+
+        self.actor.run(event, env)
+    }
+}
+
+impl WithholdingActor {
+    pub fn new(actor: HonestActor, protocol_config: ProtocolConfig, release: Release) -> Self {
+        WithholdingActor {
+            actor,
+            protocol_config,
+            release,
+            withheld: None,
+        }
+    }
+
+    /// Releases the produced block to our peers and our own protocol after the configured delay.
+    /// Our own protocol verifies the block again at that time and rejects it if the view has changed meanwhile.
+    fn withhold(&mut self, block: &Block, env: &mut Environment<Event, MetricsEventType>) {
+        let delay = self.release.delay(&self.protocol_config, block.view_number());
+        env.note_event(&MetricsEventType::Withheld {
+            own: env.own_id(),
+            hash: block.hash(),
+            delay,
+        }, env.time());
+
+        self.withheld = Some((block.block_number(), block.view_number()));
+        let release_time = env.time() + delay;
+        env.scheduled_broadcast(Event::Block(block.clone()), release_time);
+        env.schedule_self(Event::Block(block.clone()), release_time);
+    }
+}
@@ -3,29 +3,52 @@ use std::time::Duration;
 
 use clap::{App, Arg, Values};
 
+use crate::protocol::Aggregation;
+use crate::protocol::Backoff;
+use crate::protocol::Dissemination;
+use crate::protocol::ProtocolVariant;
+
 #[derive(Debug, Copy, Clone)]
 pub(crate) enum ParseError {
     NumNodes,
+    NumValidators,
+    NumLightNodes,
     NumMicroBlocks,
     Blocks,
     Iterations,
     MicroBlockTimeout,
     MacroBlockTimeout,
+    MinBlockTime,
+    Backoff,
+    MaxTimeout,
+    Dissemination,
+    Aggregation,
+    Protocol,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Options {
     pub num_nodes: Vec<usize>,
+    pub num_validators: Option<u16>,
+    pub num_light_nodes: usize,
     pub num_micro_blocks: Option<u32>,
     pub blocks: u32,
     pub iterations: usize,
     pub network_settings: Option<String>,
     pub timing_settings: Option<String>,
     pub protocol_settings: Option<String>,
+    pub scenario_settings: Option<String>,
     pub trace_file: Option<String>,
+    pub producer_statistics: bool,
 
     pub micro_block_timeout: Option<Duration>,
     pub macro_block_timeout: Option<Duration>,
+    pub min_block_time: Option<Duration>,
+    pub backoff: Option<Backoff>,
+    pub max_timeout: Option<Duration>,
+    pub dissemination: Option<Dissemination>,
+    pub aggregation: Option<Aggregation>,
+    pub protocol: Option<ProtocolVariant>,
 }
 
 
@@ -38,10 +61,21 @@ impl Options {
             // Configuration
             .arg(Arg::with_name("num_nodes")
                 .value_name("NUM_NODES")
-                .help("Number of nodes in the network (currently equal to number of validators).")
+                .help("Number of nodes in the network.")
                 .takes_value(true)
                 .required(true)
                 .use_delimiter(true))
+            .arg(Arg::with_name("num_validators")
+                .long("num_validators")
+                .value_name("NUM_VALIDATORS")
+                .help("Number of validators among the nodes (defaults to all nodes).")
+                .takes_value(true))
+            .arg(Arg::with_name("num_light_nodes")
+                .long("num_light_nodes")
+                .value_name("NUM_LIGHT_NODES")
+                .help("Number of light nodes following only macro blocks, in addition to the nodes.")
+                .default_value("0")
+                .takes_value(true))
             .arg(Arg::with_name("num_micro_blocks")
                 .long("num_micro_blocks")
                 .value_name("NUM_MICRO_BLOCKS")
@@ -80,12 +114,22 @@ impl Options {
                 .help("Path to the protocol settings.")
                 .default_value("./config/protocol.toml")
                 .takes_value(true))
+            .arg(Arg::with_name("scenario_settings")
+                .long("scenario_settings_file")
+                .short("s")
+                .value_name("SCENARIO_SETTINGS_FILE")
+                .help("Path to the scenario settings, which determine the validators deviating from the protocol.")
+                .default_value("./config/scenario.toml")
+                .takes_value(true))
             .arg(Arg::with_name("trace_file")
                 .long("trace_file")
                 .short("l")
                 .value_name("TRACE_FILE")
                 .help("Allows to store all events in a trace file (only useful for a single iteration and configuration only).")
                 .takes_value(false))
+            .arg(Arg::with_name("producer_statistics")
+                .long("producer_statistics")
+                .help("Records the sequence of block producers and tests it for uniformity across validators."))
             .arg(Arg::with_name("micro_block_timeout")
                 .long("micro_block_timeout")
                 .value_name("MICRO_BLOCK_TIMEOUT")
@@ -96,6 +140,40 @@ impl Options {
                 .value_name("MACRO_BLOCK_TIMEOUT")
                 .help("Allows to override the macro block timeout from the timing config.")
                 .takes_value(true))
+            .arg(Arg::with_name("min_block_time")
+                .long("min_block_time")
+                .value_name("MIN_BLOCK_TIME")
+                .help("Allows to override the minimum block time (in µs) from the protocol config.")
+                .takes_value(true))
+            .arg(Arg::with_name("backoff")
+                .long("backoff")
+                .value_name("BACKOFF")
+                .help("Allows to override how timeouts grow with view changes (constant, linear, exponential or adaptive).")
+                .possible_values(&["constant", "linear", "exponential", "adaptive"])
+                .takes_value(true))
+            .arg(Arg::with_name("max_timeout")
+                .long("max_timeout")
+                .value_name("MAX_TIMEOUT")
+                .help("Allows to override the maximum block timeout (in µs) from the protocol config.")
+                .takes_value(true))
+            .arg(Arg::with_name("dissemination")
+                .long("dissemination")
+                .value_name("DISSEMINATION")
+                .help("Allows to override how PBFT messages are disseminated (flood or validators).")
+                .possible_values(&["flood", "validators"])
+                .takes_value(true))
+            .arg(Arg::with_name("aggregation")
+                .long("aggregation")
+                .value_name("AGGREGATION")
+                .help("Allows to override how prepare and commit messages are aggregated (all-to-all or handel).")
+                .possible_values(&["all-to-all", "handel"])
+                .takes_value(true))
+            .arg(Arg::with_name("protocol")
+                .long("protocol")
+                .value_name("PROTOCOL")
+                .help("Allows to override the consensus protocol run by the validators (albatross).")
+                .possible_values(&["albatross"])
+                .takes_value(true))
     }
 
     /// Parses a command line option from a string into `T` and returns `error`, when parsing fails.
@@ -141,17 +219,29 @@ impl Options {
 
         Ok(Options {
             num_nodes: Self::parse_values::<usize>(matches.values_of("num_nodes"), ParseError::NumNodes)?,
+            num_validators: Self::parse_option::<u16>(matches.value_of("num_validators"), ParseError::NumValidators)?,
+            num_light_nodes: Self::parse_value::<usize>(matches.value_of("num_light_nodes"), ParseError::NumLightNodes)?,
             num_micro_blocks: Self::parse_option::<u32>(matches.value_of("num_micro_blocks"), ParseError::NumMicroBlocks)?,
             blocks: Self::parse_value::<u32>(matches.value_of("blocks"), ParseError::Blocks)?,
             iterations: Self::parse_value::<usize>(matches.value_of("iterations"), ParseError::Iterations)?,
             network_settings: Self::parse_option_string(matches.value_of("network_settings")),
             timing_settings: Self::parse_option_string(matches.value_of("timing_settings")),
             protocol_settings: Self::parse_option_string(matches.value_of("protocol_settings")),
+            scenario_settings: Self::parse_option_string(matches.value_of("scenario_settings")),
             trace_file: Self::parse_option_string(matches.value_of("trace_file")),
+            producer_statistics: matches.is_present("producer_statistics"),
             micro_block_timeout: Self::parse_option::<u64>(matches.value_of("micro_block_timeout"), ParseError::MicroBlockTimeout)?
                 .map(Duration::from_micros),
             macro_block_timeout: Self::parse_option::<u64>(matches.value_of("macro_block_timeout"), ParseError::MacroBlockTimeout)?
                 .map(Duration::from_micros),
+            min_block_time: Self::parse_option::<u64>(matches.value_of("min_block_time"), ParseError::MinBlockTime)?
+                .map(Duration::from_micros),
+            backoff: Self::parse_option::<Backoff>(matches.value_of("backoff"), ParseError::Backoff)?,
+            max_timeout: Self::parse_option::<u64>(matches.value_of("max_timeout"), ParseError::MaxTimeout)?
+                .map(Duration::from_micros),
+            dissemination: Self::parse_option::<Dissemination>(matches.value_of("dissemination"), ParseError::Dissemination)?,
+            aggregation: Self::parse_option::<Aggregation>(matches.value_of("aggregation"), ParseError::Aggregation)?,
+            protocol: Self::parse_option::<ProtocolVariant>(matches.value_of("protocol"), ParseError::Protocol)?,
         })
     }
 }
\ No newline at end of file
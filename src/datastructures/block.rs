@@ -2,10 +2,15 @@ use std::collections::HashSet;
 use std::fmt;
 use std::time::Duration;
 
+use crate::actors::GenerationTime;
 use crate::actors::Timing;
 use crate::actors::VerificationTime;
+use crate::datastructures::ByteSize;
+use crate::datastructures::Encode;
 use crate::datastructures::hash::Hash;
 use crate::datastructures::hash::Hasher;
+use crate::datastructures::merkle;
+use crate::datastructures::merkle::MerkleProof;
 use crate::datastructures::pbft::PbftJustification;
 use crate::datastructures::pbft::ViewChangeProof;
 use crate::datastructures::signature::KeyPair;
@@ -50,6 +55,20 @@ impl Block {
         }
     }
 
+    pub fn parent_hash(&self) -> &Hash {
+        match self {
+            Block::Macro(ref block) => &block.header.parent_hash,
+            Block::Micro(ref block) => &block.header.parent_hash,
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            Block::Macro(ref block) => block.extrinsics.timestamp,
+            Block::Micro(ref block) => block.extrinsics.timestamp,
+        }
+    }
+
     pub fn seed(&self) -> &Signature<Seed> {
         match self {
             Block::Macro(ref block) => &block.extrinsics.seed,
@@ -65,6 +84,33 @@ impl Block {
     }
 }
 
+impl ByteSize for Block {
+    fn byte_size(&self) -> usize {
+        match self {
+            Block::Macro(ref block) => block.byte_size(),
+            Block::Micro(ref block) => block.byte_size(),
+        }
+    }
+}
+
+impl VerificationTime for Block {
+    fn verification_time(&self, timing: &Timing) -> Duration {
+        match self {
+            Block::Macro(ref block) => block.verification_time(timing),
+            Block::Micro(ref block) => block.verification_time(timing),
+        }
+    }
+}
+
+impl GenerationTime for Block {
+    fn generation_time(&self, timing: &Timing) -> Duration {
+        match self {
+            Block::Macro(ref block) => block.generation_time(timing),
+            Block::Micro(ref block) => block.generation_time(timing),
+        }
+    }
+}
+
 impl fmt::Display for Block {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(f, "[#{}, view {}, type {:?}]", self.block_number(), self.view_number(), self.block_type())
@@ -107,6 +153,12 @@ impl MacroDigest {
     }
 }
 
+impl ByteSize for MacroDigest {
+    fn byte_size(&self) -> usize {
+        self.validators.byte_size() + self.parent_macro_hash.byte_size() + 4 + 2
+    }
+}
+
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub struct MicroDigest {
     pub validator: PublicKey,
@@ -124,6 +176,12 @@ impl MicroDigest {
     }
 }
 
+impl ByteSize for MicroDigest {
+    fn byte_size(&self) -> usize {
+        self.validator.byte_size() + 4 + 2
+    }
+}
+
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub struct MacroHeader {
     pub parent_hash: Hash,
@@ -136,13 +194,29 @@ impl MacroHeader {
     pub fn hash(&self) -> Hash {
         Hasher::default()
             .chain(&self.parent_hash)
-            .chain(&self.digest.to_bytes())
+            .chain(self.digest.to_bytes())
             .chain(&self.extrinsics_root)
             .chain(&self.state_root)
             .result()
     }
 }
 
+impl ByteSize for MacroHeader {
+    fn byte_size(&self) -> usize {
+        self.parent_hash.byte_size() + self.digest.byte_size()
+            + self.extrinsics_root.byte_size() + self.state_root.byte_size()
+    }
+}
+
+impl Encode for MacroHeader {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.parent_hash.encode(buffer);
+        buffer.extend_from_slice(&self.digest.to_bytes());
+        self.extrinsics_root.encode(buffer);
+        self.state_root.encode(buffer);
+    }
+}
+
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub struct MicroHeader {
     pub parent_hash: Hash,
@@ -155,13 +229,29 @@ impl MicroHeader {
     pub fn hash(&self) -> Hash {
         Hasher::default()
             .chain(&self.parent_hash)
-            .chain(&self.digest.to_bytes())
+            .chain(self.digest.to_bytes())
             .chain(&self.extrinsics_root)
             .chain(&self.state_root)
             .result()
     }
 }
 
+impl ByteSize for MicroHeader {
+    fn byte_size(&self) -> usize {
+        self.parent_hash.byte_size() + self.digest.byte_size()
+            + self.extrinsics_root.byte_size() + self.state_root.byte_size()
+    }
+}
+
+impl Encode for MicroHeader {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.parent_hash.encode(buffer);
+        buffer.extend_from_slice(&self.digest.to_bytes());
+        self.extrinsics_root.encode(buffer);
+        self.state_root.encode(buffer);
+    }
+}
+
 impl fmt::Display for MicroHeader {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(f, "[#{} view {}, type Micro]", self.digest.block_number, self.digest.view_number)
@@ -176,9 +266,23 @@ pub struct MacroExtrinsics {
 }
 
 impl MacroExtrinsics {
+    fn leaves(&self) -> Vec<Hash> {
+        vec![
+            merkle::hash_leaf(&self.timestamp),
+            merkle::hash_leaf(&self.seed),
+            merkle::hash_leaf(&self.view_change_messages),
+        ]
+    }
+
+    /// Returns the Merkle root of the extrinsics.
     pub fn hash(&self) -> Hash {
-        // TODO: Implement hash.
-        Hash::default()
+        merkle::compute_root(&self.leaves())
+    }
+}
+
+impl ByteSize for MacroExtrinsics {
+    fn byte_size(&self) -> usize {
+        8 + self.seed.byte_size() + self.view_change_messages.byte_size()
     }
 }
 
@@ -192,9 +296,37 @@ pub struct MicroExtrinsics {
 }
 
 impl MicroExtrinsics {
+    /// Leaves are the timestamp, the seed, the view change messages,
+    /// followed by each slash inherent and each transaction.
+    fn leaves(&self) -> Vec<Hash> {
+        let mut leaves = Vec::with_capacity(3 + self.slash_inherents.len() + self.transactions.len());
+        leaves.push(merkle::hash_leaf(&self.timestamp));
+        leaves.push(merkle::hash_leaf(&self.seed));
+        leaves.push(merkle::hash_leaf(&self.view_change_messages));
+        leaves.extend(self.slash_inherents.iter().map(merkle::hash_leaf));
+        leaves.extend(self.transactions.iter().map(merkle::hash_leaf));
+        leaves
+    }
+
+    /// Returns the Merkle root of the extrinsics.
     pub fn hash(&self) -> Hash {
-        // TODO: Implement hash.
-        Hash::default()
+        merkle::compute_root(&self.leaves())
+    }
+
+    /// Proves the inclusion of the transaction at the given index in the extrinsics root.
+    /// The proof is verified against `merkle::hash_leaf(transaction)`.
+    pub fn transaction_proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.transactions.len() {
+            return None;
+        }
+        MerkleProof::create(&self.leaves(), 3 + self.slash_inherents.len() + index)
+    }
+}
+
+impl ByteSize for MicroExtrinsics {
+    fn byte_size(&self) -> usize {
+        8 + self.seed.byte_size() + self.view_change_messages.byte_size()
+            + self.slash_inherents.byte_size() + self.transactions.byte_size()
     }
 }
 
@@ -242,6 +374,12 @@ impl MacroBlock {
     }
 }
 
+impl ByteSize for MacroBlock {
+    fn byte_size(&self) -> usize {
+        self.header.byte_size() + self.extrinsics.byte_size() + self.justification.byte_size()
+    }
+}
+
 impl VerificationTime for MacroBlock {
     fn verification_time(&self, timing: &Timing) -> Duration {
         let mut time = self.extrinsics.seed.verification_time(timing);
@@ -258,6 +396,22 @@ impl VerificationTime for MacroBlock {
     }
 }
 
+impl GenerationTime for MacroBlock {
+    fn generation_time(&self, timing: &Timing) -> Duration {
+        let mut time = self.extrinsics.seed.generation_time(timing);
+
+        if let Some(ref proof) = self.extrinsics.view_change_messages {
+            time += proof.generation_time(timing);
+        }
+
+        if let Some(ref justification) = self.justification {
+            time += justification.generation_time(timing);
+        }
+
+        time
+    }
+}
+
 impl PartialEq for MacroBlock {
     fn eq(&self, other: &MacroBlock) -> bool {
         self.header == other.header
@@ -282,6 +436,12 @@ pub struct MicroBlock {
     pub justification: Signature<MicroHeader>,
 }
 
+impl ByteSize for MicroBlock {
+    fn byte_size(&self) -> usize {
+        self.header.byte_size() + self.extrinsics.byte_size() + self.justification.byte_size()
+    }
+}
+
 impl VerificationTime for MicroBlock {
     fn verification_time(&self, timing: &Timing) -> Duration {
         let mut time = self.extrinsics.seed.verification_time(timing)
@@ -299,6 +459,20 @@ impl VerificationTime for MicroBlock {
     }
 }
 
+impl GenerationTime for MicroBlock {
+    fn generation_time(&self, timing: &Timing) -> Duration {
+        // Transactions and slash inherents are included as received, only their verification counts.
+        let mut time = self.extrinsics.seed.generation_time(timing)
+            + self.justification.generation_time(timing);
+
+        if let Some(ref proof) = self.extrinsics.view_change_messages {
+            time += proof.generation_time(timing);
+        }
+
+        time
+    }
+}
+
 impl PartialEq for MicroBlock {
     fn eq(&self, other: &MicroBlock) -> bool {
         self.header == other.header
@@ -307,3 +481,50 @@ impl PartialEq for MicroBlock {
 }
 
 impl Eq for MicroBlock {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(value: u64) -> Transaction {
+        Transaction {
+            sender: KeyPair::from_id(0).public_key(),
+            recipient: KeyPair::from_id(1).public_key(),
+            value,
+            fee: 0,
+            validity_start_height: 0,
+        }
+    }
+
+    fn extrinsics(num_transactions: u64) -> MicroExtrinsics {
+        MicroExtrinsics {
+            timestamp: 0,
+            seed: KeyPair::from_id(0).secret_key().sign(&Hash::default()),
+            view_change_messages: None,
+            slash_inherents: Vec::new(),
+            transactions: (0..num_transactions).map(transaction).collect(),
+        }
+    }
+
+    #[test]
+    fn transaction_proof() {
+        for num_transactions in 1..=6 {
+            let extrinsics = extrinsics(num_transactions);
+            let root = extrinsics.hash();
+            for (index, included) in extrinsics.transactions.iter().enumerate() {
+                let proof = extrinsics.transaction_proof(index).unwrap();
+                assert!(proof.verify(&merkle::hash_leaf(included), &root));
+                assert!(!proof.verify(&merkle::hash_leaf(&transaction(num_transactions)), &root));
+            }
+            assert!(extrinsics.transaction_proof(extrinsics.transactions.len()).is_none());
+        }
+    }
+
+    #[test]
+    fn root_commits_to_transactions() {
+        let mut extrinsics = extrinsics(3);
+        let root = extrinsics.hash();
+        extrinsics.transactions[1].value += 1;
+        assert_ne!(extrinsics.hash(), root);
+    }
+}
@@ -0,0 +1,73 @@
+//! BLS12-381 signatures in the min-signature-size variant (signatures in G1, public keys in G2).
+//! Secret keys are derived from the key ids, such that every node can derive all public keys.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use bls12_381::{G1Affine, G1Projective, G2Affine, G2Projective, Gt, pairing, Scalar};
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls_sha2::{Digest, Sha256, Sha512};
+
+/// Domain separation tag of the hash to G1.
+const DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_";
+
+pub type CompressedSignature = [u8; 48];
+
+fn secret_key(id: u64) -> Scalar {
+    let mut bytes = [0u8; 64];
+    bytes.copy_from_slice(&Sha512::digest(&id.to_be_bytes()));
+    Scalar::from_bytes_wide(&bytes)
+}
+
+/// Returns the public key of the key pair with the given id.
+/// Public keys are cached, since deriving them dominates the cost of verifying aggregates.
+pub fn public_key(id: u64) -> G2Affine {
+    static PUBLIC_KEYS: OnceLock<Mutex<HashMap<u64, G2Affine>>> = OnceLock::new();
+
+    *PUBLIC_KEYS.get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .entry(id)
+        .or_insert_with(|| G2Affine::from(G2Affine::generator() * secret_key(id)))
+}
+
+fn hash_to_curve(message: &[u8]) -> G1Affine {
+    G1Affine::from(<G1Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(message, DST))
+}
+
+pub fn sign(id: u64, message: &[u8]) -> CompressedSignature {
+    G1Affine::from(hash_to_curve(message) * secret_key(id)).to_compressed()
+}
+
+pub fn decompress(signature: &CompressedSignature) -> Option<G1Projective> {
+    Option::<G1Affine>::from(G1Affine::from_compressed(signature)).map(G1Projective::from)
+}
+
+pub fn compress(signature: &G1Projective) -> CompressedSignature {
+    G1Affine::from(signature).to_compressed()
+}
+
+/// Adds up signatures. Returns `None` if any of them is not a valid point.
+pub fn aggregate<'a, I: IntoIterator<Item=&'a CompressedSignature>>(signatures: I) -> Option<G1Projective> {
+    signatures.into_iter()
+        .map(decompress)
+        .sum()
+}
+
+/// Verifies a signature of all given keys on the same message.
+pub fn verify(signature: &G1Projective, ids: &[u64], message: &[u8]) -> bool {
+    let aggregate_key: G2Projective = ids.iter()
+        .map(|&id| G2Projective::from(public_key(id)))
+        .sum();
+    pairing(&G1Affine::from(signature), &G2Affine::generator())
+        == pairing(&hash_to_curve(message), &G2Affine::from(aggregate_key))
+}
+
+/// Verifies a signature of the given keys on one message per key.
+pub fn verify_multiple(signature: &G1Projective, ids: &[u64], messages: &[Vec<u8>]) -> bool {
+    let expected: Gt = ids.iter().zip(messages)
+        .map(|(&id, message)| pairing(&hash_to_curve(message), &public_key(id)))
+        .sum();
+    pairing(&G1Affine::from(signature), &G2Affine::generator()) == expected
+}
@@ -1,6 +1,9 @@
 use sha2::{Digest, Sha256};
 use std::fmt;
 
+use crate::datastructures::ByteSize;
+use crate::datastructures::Encode;
+
 #[derive(Default)]
 pub struct Hasher(Sha256);
 
@@ -23,6 +26,7 @@ impl Hasher {
 pub struct Hash([u8; 32]);
 
 impl Hash {
+    #[allow(clippy::self_named_constructors)]
     pub fn hash<T: AsRef<[u8]>>(data: T) -> Self {
         Hasher::default().chain(data).result()
     }
@@ -32,6 +36,18 @@ impl Hash {
     }
 }
 
+impl ByteSize for Hash {
+    fn byte_size(&self) -> usize {
+        32
+    }
+}
+
+impl Encode for Hash {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.0);
+    }
+}
+
 impl<'a> From<&'a [u8]> for Hash {
     fn from(slice: &'a [u8]) -> Self {
         assert_eq!(slice.len(), 32, "Tried to create instance with slice of wrong length");
@@ -61,6 +77,6 @@ impl From<Hash> for Vec<u8> {
 
 impl fmt::Debug for Hash {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{}", hex::encode(&self.0))
+        write!(f, "{}", hex::encode(self.0))
     }
 }
@@ -0,0 +1,147 @@
+use crate::datastructures::ByteSize;
+use crate::datastructures::Encode;
+use crate::datastructures::hash::Hash;
+use crate::datastructures::hash::Hasher;
+
+// Prefixes separate the hashes of leaves from those of inner nodes,
+// such that an inner node cannot be passed off as a leaf.
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Hashes the encoding of a leaf.
+pub fn hash_leaf<T: Encode + ?Sized>(leaf: &T) -> Hash {
+    Hasher::default()
+        .chain([LEAF_PREFIX])
+        .chain(leaf.to_encoded_bytes())
+        .result()
+}
+
+fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    Hasher::default()
+        .chain([NODE_PREFIX])
+        .chain(left)
+        .chain(right)
+        .result()
+}
+
+/// Computes the next level of the tree.
+/// A node without a sibling is promoted to the next level unchanged.
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level.chunks(2).map(|pair| match pair {
+        [left, right] => hash_node(left, right),
+        [single] => single.clone(),
+        _ => unreachable!(),
+    }).collect()
+}
+
+/// Computes the Merkle root of the given leaf hashes.
+/// The root of an empty tree is the default hash.
+pub fn compute_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return Hash::default();
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.pop().unwrap()
+}
+
+/// A sibling on the path from a leaf to the root.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MerkleNode {
+    Left(Hash),
+    Right(Hash),
+}
+
+/// Proves the inclusion of a leaf in a Merkle tree.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MerkleProof {
+    pub path: Vec<MerkleNode>,
+}
+
+impl MerkleProof {
+    /// Creates a proof for the leaf at the given index.
+    pub fn create(leaves: &[Hash], mut index: usize) -> Option<Self> {
+        if index >= leaves.len() {
+            return None;
+        }
+
+        let mut path = Vec::new();
+        let mut level = leaves.to_vec();
+        while level.len() > 1 {
+            if index % 2 == 1 {
+                path.push(MerkleNode::Left(level[index - 1].clone()));
+            } else if let Some(sibling) = level.get(index + 1) {
+                path.push(MerkleNode::Right(sibling.clone()));
+            }
+
+            level = next_level(&level);
+            index /= 2;
+        }
+
+        Some(MerkleProof { path })
+    }
+
+    /// Computes the root of the tree the leaf is included in according to this proof.
+    pub fn compute_root(&self, leaf: &Hash) -> Hash {
+        self.path.iter().fold(leaf.clone(), |hash, node| match node {
+            MerkleNode::Left(sibling) => hash_node(sibling, &hash),
+            MerkleNode::Right(sibling) => hash_node(&hash, sibling),
+        })
+    }
+
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> bool {
+        self.compute_root(leaf) == *root
+    }
+}
+
+impl ByteSize for MerkleProof {
+    fn byte_size(&self) -> usize {
+        // Number of nodes, a bitmap of their sides and the hashes.
+        2 + self.path.len().div_ceil(8) + self.path.len() * 32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: u32) -> Vec<Hash> {
+        (0..n).map(|i| hash_leaf(&i)).collect()
+    }
+
+    #[test]
+    fn root() {
+        assert_eq!(compute_root(&[]), Hash::default());
+
+        let leaves = leaves(3);
+        assert_eq!(compute_root(&leaves[..1]), leaves[0]);
+        assert_eq!(compute_root(&leaves[..2]), hash_node(&leaves[0], &leaves[1]));
+        // The last leaf of an odd level is promoted.
+        assert_eq!(compute_root(&leaves), hash_node(&hash_node(&leaves[0], &leaves[1]), &leaves[2]));
+    }
+
+    #[test]
+    fn proof_round_trip() {
+        for n in 1..=9 {
+            let leaves = leaves(n);
+            let root = compute_root(&leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = MerkleProof::create(&leaves, index).unwrap();
+                assert!(proof.verify(leaf, &root), "leaf {} of {}", index, n);
+                assert!(!proof.verify(&hash_leaf(&n), &root), "leaf {} of {}", index, n);
+            }
+            assert!(MerkleProof::create(&leaves, leaves.len()).is_none());
+        }
+    }
+
+    #[test]
+    fn single_leaf_proof() {
+        let leaves = leaves(1);
+        let proof = MerkleProof::create(&leaves, 0).unwrap();
+        assert!(proof.path.is_empty());
+        assert!(proof.verify(&leaves[0], &leaves[0]));
+    }
+}
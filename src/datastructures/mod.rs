@@ -1,6 +1,96 @@
 pub mod block;
+#[cfg(feature = "bls")]
+pub mod bls;
 pub mod signature;
 pub mod hash;
+pub mod merkle;
 pub mod pbft;
 pub mod slashing;
+pub mod sync;
 pub mod transaction;
+
+/// Approximates the number of bytes a datastructure occupies on the wire.
+/// Sizes refer to the real protocol (e.g., BLS signatures), not to the simulated datastructures.
+pub trait ByteSize {
+    fn byte_size(&self) -> usize;
+}
+
+impl<T: ByteSize + ?Sized> ByteSize for Box<T> {
+    fn byte_size(&self) -> usize {
+        (**self).byte_size()
+    }
+}
+
+impl<T: ByteSize> ByteSize for Option<T> {
+    fn byte_size(&self) -> usize {
+        1 + self.as_ref().map(ByteSize::byte_size).unwrap_or(0)
+    }
+}
+
+impl<T: ByteSize> ByteSize for Vec<T> {
+    fn byte_size(&self) -> usize {
+        2 + self.iter().map(ByteSize::byte_size).sum::<usize>()
+    }
+}
+
+/// Deterministic byte encoding of a datastructure, used to compute hashes and Merkle roots.
+pub trait Encode {
+    fn encode(&self, buffer: &mut Vec<u8>);
+
+    fn to_encoded_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.encode(&mut buffer);
+        buffer
+    }
+}
+
+impl Encode for u16 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (**self).encode(buffer)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                buffer.push(1);
+                value.encode(buffer);
+            },
+            None => buffer.push(0),
+        }
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (self.len() as u16).encode(buffer);
+        for item in self {
+            item.encode(buffer);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.as_slice().encode(buffer)
+    }
+}
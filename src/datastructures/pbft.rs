@@ -5,8 +5,13 @@ use std::hash::Hash;
 use std::hash::Hasher;
 use std::time::Duration;
 
+use crate::actors::GenerationTime;
 use crate::actors::Timing;
 use crate::actors::VerificationTime;
+use crate::datastructures::ByteSize;
+use crate::datastructures::Encode;
+use crate::datastructures::block::MacroBlock;
+use crate::datastructures::block::MacroHeader;
 use crate::datastructures::hash::Hash as ShaHash;
 use crate::datastructures::signature::{AggregatePublicKey, PublicKey};
 use crate::datastructures::signature::AggregateSignature;
@@ -15,19 +20,33 @@ use crate::datastructures::signature::Signature;
 
 #[derive(Clone, Debug)]
 pub struct PbftJustification {
-    pub prepare: AggregateProof<ShaHash>,
-    pub commit: AggregateProof<ShaHash>,
+    /// The view in which the prepares were cast.
+    pub view_number: u16,
+    pub prepare: AggregateProof<PbftMessage>,
+    pub commit: AggregateProof<PbftMessage>,
 }
 
 impl PbftJustification {
-    pub fn verify(&self, validators: &[PublicKey], hash: &ShaHash) -> bool {
+    /// Checks that more than `threshold` distinct validators prepared and committed the proposal.
+    pub fn verify(&self, validators: &[PublicKey], hash: &ShaHash, threshold: u16) -> bool {
+        if !self.prepare.has_distinct_signers(validators.len()) || !self.commit.has_distinct_signers(validators.len())
+            || self.prepare.num_signers() <= usize::from(threshold) || self.commit.num_signers() <= usize::from(threshold) {
+            return false;
+        }
+
         let aggregate_key = AggregatePublicKey::from(get_validators(validators, &self.prepare.public_key_bitmap));
-        if !self.prepare.signatures.verify_single(&aggregate_key, hash) {
+        if !self.prepare.signatures.verify_single(&aggregate_key, &PbftMessage::Prepare(hash.clone(), self.view_number)) {
             return false;
         }
 
         let aggregate_key = AggregatePublicKey::from(get_validators(validators, &self.commit.public_key_bitmap));
-        self.commit.signatures.verify_single(&aggregate_key, hash)
+        self.commit.signatures.verify_single(&aggregate_key, &PbftMessage::Commit(hash.clone()))
+    }
+}
+
+impl ByteSize for PbftJustification {
+    fn byte_size(&self) -> usize {
+        2 + self.prepare.byte_size() + self.commit.byte_size()
     }
 }
 
@@ -37,22 +56,112 @@ impl VerificationTime for PbftJustification {
     }
 }
 
+impl GenerationTime for PbftJustification {
+    fn generation_time(&self, timing: &Timing) -> Duration {
+        self.prepare.generation_time(timing) + self.commit.generation_time(timing)
+    }
+}
+
+/// The content signed in the two PBFT phases.
+/// The phase is part of the signed message, such that a prepare cannot be replayed as a commit.
+/// Prepares also sign the view they are cast in, such that they cannot be presented as a certificate of a later view.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum PbftMessage {
+    Prepare(ShaHash, u16),
+    Commit(ShaHash),
+}
+
+impl PbftMessage {
+    /// The hash of the proposal voted for.
+    pub fn hash(&self) -> &ShaHash {
+        match self {
+            PbftMessage::Prepare(hash, _) | PbftMessage::Commit(hash) => hash,
+        }
+    }
+}
+
+impl ByteSize for PbftMessage {
+    fn byte_size(&self) -> usize {
+        match self {
+            // Phase, hash and view.
+            PbftMessage::Prepare(hash, _) => 1 + hash.byte_size() + 2,
+            // Phase and hash.
+            PbftMessage::Commit(hash) => 1 + hash.byte_size(),
+        }
+    }
+}
+
+impl Encode for PbftMessage {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        match self {
+            PbftMessage::Prepare(hash, view_number) => {
+                buffer.push(0);
+                hash.encode(buffer);
+                view_number.encode(buffer);
+            },
+            PbftMessage::Commit(hash) => {
+                buffer.push(1);
+                hash.encode(buffer);
+            },
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PbftProof {
-    pub signature: Signature<ShaHash>,
+    pub signature: Signature<PbftMessage>,
+    hash: ShaHash,
     id: PublicKey,
 }
 
 impl PbftProof {
-    pub fn new(hash: &ShaHash, key: &SecretKey) -> Self {
+    pub fn prepare(hash: &ShaHash, view_number: u16, key: &SecretKey) -> Self {
+        Self::new(PbftMessage::Prepare(hash.clone(), view_number), hash, key)
+    }
+
+    pub fn commit(hash: &ShaHash, key: &SecretKey) -> Self {
+        Self::new(PbftMessage::Commit(hash.clone()), hash, key)
+    }
+
+    fn new(message: PbftMessage, hash: &ShaHash, key: &SecretKey) -> Self {
         PbftProof {
-            signature: key.sign(&hash),
+            signature: key.sign(&message),
+            hash: hash.clone(),
             id: key.into(),
         }
     }
 
-    pub fn verify(&self, hash: &ShaHash) -> bool {
-        self.signature.verify(&self.id, &hash)
+    /// Recovers the vote of a single validator from its signature.
+    pub fn from_signature(signature: Signature<PbftMessage>) -> Self {
+        let hash = signature.message().hash().clone();
+        PbftProof {
+            id: signature.public_key().clone(),
+            signature,
+            hash,
+        }
+    }
+
+    /// The hash of the proposal this message votes for.
+    pub fn hash(&self) -> &ShaHash {
+        &self.hash
+    }
+
+    pub fn verify_prepare(&self) -> bool {
+        match self.signature.message() {
+            PbftMessage::Prepare(hash, _) if *hash == self.hash => self.signature.verify(&self.id, self.signature.message()),
+            _ => false,
+        }
+    }
+
+    pub fn verify_commit(&self) -> bool {
+        self.signature.verify(&self.id, &PbftMessage::Commit(self.hash.clone()))
+    }
+}
+
+impl ByteSize for PbftProof {
+    fn byte_size(&self) -> usize {
+        // Signers are identified by their validator index.
+        self.hash.byte_size() + self.signature.byte_size() + 2
     }
 }
 
@@ -84,6 +193,59 @@ impl fmt::Display for PbftProof {
     }
 }
 
+/// Proof that more than two thirds of the validators prepared a proposal in a view.
+#[derive(Clone, Debug)]
+pub struct PrepareCertificate {
+    pub view_number: u16,
+    pub prepare: AggregateProof<PbftMessage>,
+}
+
+impl PrepareCertificate {
+    /// Checks that more than `threshold` distinct validators prepared the proposal in the certified view.
+    pub fn verify(&self, validators: &[PublicKey], hash: &ShaHash, threshold: u16) -> bool {
+        if !self.prepare.has_distinct_signers(validators.len())
+            || self.prepare.num_signers() <= usize::from(threshold) {
+            return false;
+        }
+
+        let aggregate_key = AggregatePublicKey::from(get_validators(validators, &self.prepare.public_key_bitmap));
+        self.prepare.signatures.verify_single(&aggregate_key, &PbftMessage::Prepare(hash.clone(), self.view_number))
+    }
+}
+
+impl ByteSize for PrepareCertificate {
+    fn byte_size(&self) -> usize {
+        2 + self.prepare.byte_size()
+    }
+}
+
+impl VerificationTime for PrepareCertificate {
+    fn verification_time(&self, timing: &Timing) -> Duration {
+        self.prepare.verification_time(timing)
+    }
+}
+
+/// A prepared proposal a validator is locked on.
+/// It is carried in view change messages, so that the next leader re-proposes it.
+#[derive(Clone, Debug)]
+pub struct LockedProposal {
+    pub proposal: MacroBlock,
+    pub signature: Signature<MacroHeader>,
+    pub certificate: PrepareCertificate,
+}
+
+impl LockedProposal {
+    pub fn verify(&self, validators: &[PublicKey], threshold: u16) -> bool {
+        self.certificate.verify(validators, &self.proposal.header.hash(), threshold)
+    }
+}
+
+impl ByteSize for LockedProposal {
+    fn byte_size(&self) -> usize {
+        self.proposal.byte_size() + self.signature.byte_size() + self.certificate.byte_size()
+    }
+}
+
 /// Return a set of public keys given to a bitmap.
 /// We only need this for the current validator set, since macro blocks cannot be reverted.
 pub fn get_validators(validators: &[PublicKey], bitmap: &[u16]) -> Vec<PublicKey> {
@@ -100,15 +262,24 @@ pub struct ViewChangeInternals {
     pub new_view_number: u16,
 }
 
+impl Encode for ViewChangeInternals {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.block_number.encode(buffer);
+        self.new_view_number.encode(buffer);
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ViewChange {
     pub internals: ViewChangeInternals,
     pub signature: Signature<ViewChangeInternals>,
+    /// The proposal the sender is locked on, only for macro blocks.
+    pub locked: Option<Box<LockedProposal>>,
     id: PublicKey,
 }
 
 impl ViewChange {
-    pub fn new(block_number: u32, new_view_number: u16, key: &SecretKey) -> Self {
+    pub fn new(block_number: u32, new_view_number: u16, locked: Option<Box<LockedProposal>>, key: &SecretKey) -> Self {
         let internals = ViewChangeInternals {
             block_number,
             new_view_number,
@@ -116,6 +287,7 @@ impl ViewChange {
         ViewChange {
             signature: key.sign(&internals),
             internals,
+            locked,
             id: key.into(),
         }
     }
@@ -125,6 +297,13 @@ impl ViewChange {
     }
 }
 
+impl ByteSize for ViewChange {
+    fn byte_size(&self) -> usize {
+        // Block number, view number, signature, validator index and locked proposal.
+        4 + 2 + self.signature.byte_size() + 2 + self.locked.byte_size()
+    }
+}
+
 impl PartialEq for ViewChange {
     fn eq(&self, other: &ViewChange) -> bool {
         self.internals == other.internals
@@ -155,7 +334,7 @@ pub struct AggregateProof<T: Eq> {
     pub public_key_bitmap: Vec<u16>,
 }
 
-impl AggregateProof<ShaHash> {
+impl AggregateProof<PbftMessage> {
     pub fn create(set: &HashSet<PbftProof>, validators: &[PublicKey]) -> Self {
         let mut signatures = Vec::with_capacity(set.len());
         let mut key_bitmap = Vec::with_capacity(set.len());
@@ -178,12 +357,60 @@ impl AggregateProof<ShaHash> {
     }
 }
 
+impl AggregateProof<PbftMessage> {
+    /// Creates a proof from a single vote of the validator at the given index.
+    pub fn from_proof(proof: &PbftProof, index: u16) -> Self {
+        AggregateProof {
+            signatures: AggregateSignature::from(vec![proof.signature.clone()]),
+            public_key_bitmap: vec![index],
+        }
+    }
+}
+
+impl<T: Eq + Clone> AggregateProof<T> {
+    /// Aggregates a proof with distinct signers into this one.
+    pub fn aggregate(&mut self, other: &AggregateProof<T>) {
+        self.signatures.aggregate(&other.signatures);
+        self.public_key_bitmap.extend_from_slice(&other.public_key_bitmap);
+    }
+
+    pub fn num_signers(&self) -> usize {
+        self.public_key_bitmap.len()
+    }
+
+    /// Checks that every signer is one of `num_validators` validators and counted only once.
+    pub fn has_distinct_signers(&self, num_validators: usize) -> bool {
+        let mut signers = HashSet::with_capacity(self.public_key_bitmap.len());
+        self.public_key_bitmap.iter()
+            .all(|&index| usize::from(index) < num_validators && signers.insert(index))
+    }
+}
+
+impl<T: Eq> ByteSize for AggregateProof<T> {
+    fn byte_size(&self) -> usize {
+        self.signatures.byte_size() + 2 * self.public_key_bitmap.len()
+    }
+}
+
+impl<T: Eq + Encode> Encode for AggregateProof<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.signatures.encode(buffer);
+        self.public_key_bitmap.encode(buffer);
+    }
+}
+
 impl<T: Eq> VerificationTime for AggregateProof<T> {
     fn verification_time(&self, timing: &Timing) -> Duration {
         self.signatures.verification_time(timing) + self.public_key_bitmap.len() as u32 * timing.generate_aggregate_public_key
     }
 }
 
+impl<T: Eq> GenerationTime for AggregateProof<T> {
+    fn generation_time(&self, timing: &Timing) -> Duration {
+        self.signatures.generation_time(timing)
+    }
+}
+
 pub type ViewChangeProof = AggregateProof<ViewChangeInternals>;
 
 impl AggregateProof<ViewChangeInternals> {
@@ -208,3 +435,161 @@ impl AggregateProof<ViewChangeInternals> {
         }
     }
 }
+
+/// An aggregate of PBFT votes sent at a level of the Handel aggregation tree.
+/// The aggregate contains the signatures of the sender's subtree at that level.
+#[derive(Clone, Debug)]
+pub struct HandelContribution {
+    pub message: PbftMessage,
+    pub level: u8,
+    pub proof: AggregateProof<PbftMessage>,
+}
+
+impl ByteSize for HandelContribution {
+    fn byte_size(&self) -> usize {
+        self.message.byte_size() + 1 + self.proof.byte_size()
+    }
+}
+
+impl VerificationTime for HandelContribution {
+    fn verification_time(&self, timing: &Timing) -> Duration {
+        self.proof.verification_time(timing)
+    }
+}
+
+impl fmt::Display for HandelContribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "HandelContribution(level {}, {} signers)", self.level, self.proof.num_signers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastructures::signature::KeyPair;
+
+    fn key_pairs(n: u64) -> Vec<KeyPair> {
+        (0..n).map(KeyPair::from_id).collect()
+    }
+
+    fn public_keys(key_pairs: &[KeyPair]) -> Vec<PublicKey> {
+        key_pairs.iter().map(KeyPair::public_key).collect()
+    }
+
+    fn aggregate(proofs: Vec<PbftProof>, validators: &[PublicKey]) -> AggregateProof<PbftMessage> {
+        AggregateProof::create(&proofs.into_iter().collect(), validators)
+    }
+
+    #[test]
+    fn verify_commit_rejects_prepare() {
+        let key = KeyPair::from_id(0).secret_key();
+        let hash = ShaHash::hash("proposal");
+
+        let prepare = PbftProof::prepare(&hash, 0, &key);
+        assert!(prepare.verify_prepare());
+        assert!(!prepare.verify_commit());
+
+        let commit = PbftProof::commit(&hash, &key);
+        assert!(commit.verify_commit());
+        assert!(!commit.verify_prepare());
+    }
+
+    #[test]
+    fn justification_rejects_swapped_aggregates() {
+        let key_pairs = key_pairs(4);
+        let validators = public_keys(&key_pairs);
+        let hash = ShaHash::hash("proposal");
+
+        let prepare = aggregate(key_pairs.iter().map(|key| PbftProof::prepare(&hash, 0, &key.secret_key())).collect(), &validators);
+        let commit = aggregate(key_pairs.iter().map(|key| PbftProof::commit(&hash, &key.secret_key())).collect(), &validators);
+
+        let justification = PbftJustification {
+            view_number: 0,
+            prepare: prepare.clone(),
+            commit: commit.clone(),
+        };
+        assert!(justification.verify(&validators, &hash, 3));
+        assert!(!justification.verify(&validators, &ShaHash::hash("other proposal"), 3));
+
+        let swapped = PbftJustification {
+            view_number: 0,
+            prepare: commit.clone(),
+            commit: prepare.clone(),
+        };
+        assert!(!swapped.verify(&validators, &hash, 3));
+
+        let only_prepares = PbftJustification {
+            view_number: 0,
+            prepare: prepare.clone(),
+            commit: prepare,
+        };
+        assert!(!only_prepares.verify(&validators, &hash, 3));
+
+        let only_commits = PbftJustification {
+            view_number: 0,
+            prepare: commit.clone(),
+            commit,
+        };
+        assert!(!only_commits.verify(&validators, &hash, 3));
+    }
+
+    #[test]
+    fn justification_requires_quorum() {
+        let key_pairs = key_pairs(4);
+        let validators = public_keys(&key_pairs);
+        let hash = ShaHash::hash("proposal");
+
+        let prepare = |signers: &[KeyPair]| aggregate(signers.iter().map(|key| PbftProof::prepare(&hash, 0, &key.secret_key())).collect(), &validators);
+        let commit = |signers: &[KeyPair]| aggregate(signers.iter().map(|key| PbftProof::commit(&hash, &key.secret_key())).collect(), &validators);
+
+        let justification = |prepare, commit| PbftJustification {
+            view_number: 0,
+            prepare,
+            commit,
+        };
+        assert!(justification(prepare(&key_pairs), commit(&key_pairs)).verify(&validators, &hash, 3));
+        assert!(!justification(prepare(&key_pairs[..1]), commit(&key_pairs[..1])).verify(&validators, &hash, 3));
+        assert!(!justification(prepare(&key_pairs[..3]), commit(&key_pairs)).verify(&validators, &hash, 3));
+        assert!(!justification(prepare(&key_pairs), commit(&key_pairs[..3])).verify(&validators, &hash, 3));
+    }
+
+    fn prepare_certificate(key_pairs: &[KeyPair], hash: &ShaHash, view_number: u16) -> PrepareCertificate {
+        let validators = public_keys(key_pairs);
+        PrepareCertificate {
+            view_number,
+            prepare: aggregate(key_pairs.iter().map(|key| PbftProof::prepare(hash, view_number, &key.secret_key())).collect(), &validators),
+        }
+    }
+
+    #[test]
+    fn certificate_is_bound_to_its_view() {
+        let key_pairs = key_pairs(4);
+        let validators = public_keys(&key_pairs);
+        let hash = ShaHash::hash("proposal");
+
+        let mut certificate = prepare_certificate(&key_pairs, &hash, 1);
+        assert!(certificate.verify(&validators, &hash, 3));
+        assert!(!certificate.verify(&validators, &ShaHash::hash("other proposal"), 3));
+
+        // Prepares of view 1 do not certify the proposal in view 2.
+        certificate.view_number = 2;
+        assert!(!certificate.verify(&validators, &hash, 3));
+    }
+
+    #[test]
+    fn certificate_rejects_duplicate_signers() {
+        let key_pairs = key_pairs(4);
+        let validators = public_keys(&key_pairs);
+        let hash = ShaHash::hash("proposal");
+
+        // Two prepares listed twice each.
+        let mut certificate = prepare_certificate(&key_pairs[..2], &hash, 0);
+        certificate.prepare.public_key_bitmap.extend_from_slice(&[0, 1]);
+        assert_eq!(certificate.prepare.num_signers(), 4);
+        assert!(!certificate.verify(&validators, &hash, 3));
+
+        let mut certificate = prepare_certificate(&key_pairs[..3], &hash, 0);
+        certificate.prepare.public_key_bitmap.push(4);
+        assert!(!certificate.verify(&validators, &hash, 3));
+    }
+}
@@ -2,32 +2,69 @@ use std::collections::HashMap;
 use std::fmt;
 use std::time::Duration;
 
+use crate::actors::GenerationTime;
 use crate::actors::Timing;
 use crate::actors::VerificationTime;
+#[cfg(feature = "bls")]
+use crate::datastructures::bls;
+use crate::datastructures::ByteSize;
+use crate::datastructures::Encode;
 use crate::datastructures::hash::{Hash, Hasher};
 
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Signature<M: Eq> {
     public_key: PublicKey,
     message: M,
+    // Boxed to keep the messages containing signatures small.
+    #[cfg(feature = "bls")]
+    signature: Box<bls::CompressedSignature>,
 }
 
-impl<M: Eq> Signature<M> {
+impl<M: Eq + Encode> Signature<M> {
+    #[cfg(not(feature = "bls"))]
     pub fn verify(&self, public_key: &PublicKey, message: &M) -> bool {
         &self.public_key == public_key && &self.message == message
     }
+
+    #[cfg(feature = "bls")]
+    pub fn verify(&self, public_key: &PublicKey, message: &M) -> bool {
+        bls::decompress(&self.signature)
+            .map(|signature| bls::verify(&signature, &[public_key.id], &message.to_encoded_bytes()))
+            .unwrap_or(false)
+    }
+}
+
+impl<M: Eq> Signature<M> {
+    /// Returns the public key of the signer.
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Returns the signed message.
+    pub fn message(&self) -> &M {
+        &self.message
+    }
 }
 
 impl<M: Eq + AsRef<[u8]>> Signature<M> {
+    #[cfg(not(feature = "bls"))]
     pub fn hash(&self) -> Hash {
         // Required to generate deterministic randomness.
         // Simply hash public key and message for our simulation.
         Hasher::default()
-            .chain(&self.public_key.to_bytes())
+            .chain(self.public_key.to_bytes())
             .chain(&self.message)
             .result()
     }
 
+    #[cfg(feature = "bls")]
+    pub fn hash(&self) -> Hash {
+        // Required to generate deterministic randomness.
+        Hasher::default()
+            .chain(&self.signature[..])
+            .result()
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.hash().into()
     }
@@ -39,12 +76,45 @@ impl<M: Eq> fmt::Display for Signature<M> {
     }
 }
 
+impl<M: Eq> ByteSize for Signature<M> {
+    #[cfg(not(feature = "bls"))]
+    fn byte_size(&self) -> usize {
+        // Compressed BLS signature.
+        48
+    }
+
+    #[cfg(feature = "bls")]
+    fn byte_size(&self) -> usize {
+        self.signature.len()
+    }
+}
+
+impl<M: Eq + Encode> Encode for Signature<M> {
+    #[cfg(not(feature = "bls"))]
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        // Signatures are deterministic, so public key and message determine the signature.
+        self.public_key.encode(buffer);
+        self.message.encode(buffer);
+    }
+
+    #[cfg(feature = "bls")]
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.signature[..]);
+    }
+}
+
 impl<M: Eq> VerificationTime for Signature<M> {
     fn verification_time(&self, timing: &Timing) -> Duration {
         timing.verification
     }
 }
 
+impl<M: Eq> GenerationTime for Signature<M> {
+    fn generation_time(&self, timing: &Timing) -> Duration {
+        timing.signing
+    }
+}
+
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct KeyPair {
     id: u64,
@@ -77,6 +147,31 @@ impl PublicKey {
     pub fn to_bytes(&self) -> [u8; 8] {
         self.id.to_be_bytes()
     }
+
+    /// Returns the id the key has been created from.
+    /// In the simulation, this equals the id of the node owning the key.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl ByteSize for PublicKey {
+    #[cfg(not(feature = "bls"))]
+    fn byte_size(&self) -> usize {
+        // Compressed BLS public key.
+        96
+    }
+
+    #[cfg(feature = "bls")]
+    fn byte_size(&self) -> usize {
+        bls::public_key(self.id).to_compressed().len()
+    }
+}
+
+impl Encode for PublicKey {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_bytes());
+    }
 }
 
 impl fmt::Display for PublicKey {
@@ -91,12 +186,14 @@ pub struct SecretKey {
 }
 
 impl SecretKey {
-    pub fn sign<M: Eq + Clone>(&self, message: &M) -> Signature<M> {
+    pub fn sign<M: Eq + Clone + Encode>(&self, message: &M) -> Signature<M> {
         Signature {
             public_key: PublicKey {
                 id: self.id,
             },
             message: message.clone(),
+            #[cfg(feature = "bls")]
+            signature: Box::new(bls::sign(self.id, &message.to_encoded_bytes())),
         }
     }
 }
@@ -125,12 +222,47 @@ impl From<Vec<PublicKey>> for AggregatePublicKey {
 #[derive(Clone, Debug)]
 pub struct AggregateSignature<M: Eq> {
     signatures: HashMap<PublicKey, Signature<M>>,
+    // The sum of the individual signatures, `None` if one of them is not a valid point.
+    #[cfg(feature = "bls")]
+    aggregate: Option<Box<bls::CompressedSignature>>,
+}
+
+impl<M: Eq> ByteSize for AggregateSignature<M> {
+    #[cfg(not(feature = "bls"))]
+    fn byte_size(&self) -> usize {
+        // An aggregate signature has the size of a single signature.
+        48
+    }
+
+    #[cfg(feature = "bls")]
+    fn byte_size(&self) -> usize {
+        bls::compress(&Default::default()).len()
+    }
+}
+
+impl<M: Eq + Encode> Encode for AggregateSignature<M> {
+    #[cfg(not(feature = "bls"))]
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        // Sort by public key to be independent of the iteration order.
+        let mut signatures: Vec<_> = self.signatures.iter().collect();
+        signatures.sort_by_key(|(public_key, _)| *public_key);
+
+        (signatures.len() as u16).encode(buffer);
+        for (_, signature) in signatures {
+            signature.encode(buffer);
+        }
+    }
+
+    #[cfg(feature = "bls")]
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        let aggregate = self.aggregate.as_deref().cloned().unwrap_or_else(|| bls::compress(&Default::default()));
+        buffer.extend_from_slice(&aggregate);
+    }
 }
 
 impl<M: Eq> VerificationTime for AggregateSignature<M> {
     fn verification_time(&self, timing: &Timing) -> Duration {
-        let msg = self.signatures.values().next().map(|signature| &signature.message);
-        if self.signatures.values().all(|signature| Some(&signature.message) == msg) {
+        if self.is_same_message() {
             self.signatures.len() as u32 * timing.verify_aggregate_signature_same_message
         } else {
             self.signatures.len() as u32 * timing.verify_aggregate_signature_distinct_message
@@ -138,6 +270,16 @@ impl<M: Eq> VerificationTime for AggregateSignature<M> {
     }
 }
 
+impl<M: Eq> GenerationTime for AggregateSignature<M> {
+    fn generation_time(&self, timing: &Timing) -> Duration {
+        if self.is_same_message() {
+            self.signatures.len() as u32 * timing.generate_aggregate_signature_same_message
+        } else {
+            self.signatures.len() as u32 * timing.generate_aggregate_signature_distinct_message
+        }
+    }
+}
+
 impl<M: Eq> From<Vec<Signature<M>>> for AggregateSignature<M> {
     fn from(signatures: Vec<Signature<M>>) -> Self {
         let mut aggregated_signatures = HashMap::with_capacity(signatures.len());
@@ -145,12 +287,51 @@ impl<M: Eq> From<Vec<Signature<M>>> for AggregateSignature<M> {
             aggregated_signatures.insert(signature.public_key.clone(), signature);
         }
         AggregateSignature {
+            #[cfg(feature = "bls")]
+            aggregate: bls::aggregate(aggregated_signatures.values().map(|signature| &*signature.signature))
+                .map(|aggregate| Box::new(bls::compress(&aggregate))),
             signatures: aggregated_signatures,
         }
     }
 }
 
+impl<M: Eq + Clone> AggregateSignature<M> {
+    /// Aggregates another aggregate signature into this one.
+    pub fn aggregate(&mut self, other: &AggregateSignature<M>) {
+        #[cfg(feature = "bls")]
+        let disjoint = other.signatures.keys().all(|public_key| !self.signatures.contains_key(public_key));
+
+        for (public_key, signature) in other.signatures.iter() {
+            self.signatures.insert(public_key.clone(), signature.clone());
+        }
+
+        #[cfg(feature = "bls")] {
+            self.aggregate = match (self.aggregate.as_deref(), other.aggregate.as_deref()) {
+                (Some(own), Some(other)) if disjoint => bls::aggregate(vec![own, other]),
+                // Real aggregates with overlapping signers cannot be combined,
+                // so fall back to the individual signatures the simulation keeps.
+                _ => bls::aggregate(self.signatures.values().map(|signature| &*signature.signature)),
+            }.map(|aggregate| Box::new(bls::compress(&aggregate)));
+        }
+    }
+}
+
 impl<M: Eq> AggregateSignature<M> {
+    /// Checks whether all signatures are on the same message.
+    fn is_same_message(&self) -> bool {
+        let msg = self.signatures.values().next().map(|signature| &signature.message);
+        self.signatures.values().all(|signature| Some(&signature.message) == msg)
+    }
+
+    /// Returns the individual signatures.
+    /// Real aggregate signatures cannot be split, but the simulation keeps track of the signers.
+    pub fn signatures(&self) -> impl Iterator<Item=&Signature<M>> {
+        self.signatures.values()
+    }
+}
+
+impl<M: Eq + Encode> AggregateSignature<M> {
+    #[cfg(not(feature = "bls"))]
     pub fn verify_single(&self, public_keys: &AggregatePublicKey, message: &M) -> bool {
         for public_key in public_keys.public_keys.iter() {
             let signature = self.signatures.get(public_key);
@@ -165,6 +346,7 @@ impl<M: Eq> AggregateSignature<M> {
         true
     }
 
+    #[cfg(not(feature = "bls"))]
     pub fn verify_multiple(&self, public_keys: &AggregatePublicKey, messages: &[M]) -> bool {
         for (public_key, message) in public_keys.public_keys.iter().zip(messages) {
             let signature = self.signatures.get(public_key);
@@ -178,4 +360,23 @@ impl<M: Eq> AggregateSignature<M> {
         }
         true
     }
+
+    #[cfg(feature = "bls")]
+    pub fn verify_single(&self, public_keys: &AggregatePublicKey, message: &M) -> bool {
+        let ids: Vec<u64> = public_keys.public_keys.iter().map(PublicKey::id).collect();
+        self.aggregate.as_deref()
+            .and_then(bls::decompress)
+            .map(|aggregate| bls::verify(&aggregate, &ids, &message.to_encoded_bytes()))
+            .unwrap_or(false)
+    }
+
+    #[cfg(feature = "bls")]
+    pub fn verify_multiple(&self, public_keys: &AggregatePublicKey, messages: &[M]) -> bool {
+        let ids: Vec<u64> = public_keys.public_keys.iter().map(PublicKey::id).collect();
+        let messages: Vec<Vec<u8>> = messages.iter().map(Encode::to_encoded_bytes).collect();
+        self.aggregate.as_deref()
+            .and_then(bls::decompress)
+            .map(|aggregate| bls::verify_multiple(&aggregate, &ids, &messages))
+            .unwrap_or(false)
+    }
 }
@@ -2,6 +2,8 @@ use std::time::Duration;
 
 use crate::actors::Timing;
 use crate::actors::VerificationTime;
+use crate::datastructures::ByteSize;
+use crate::datastructures::Encode;
 use crate::datastructures::block::MicroHeader;
 use crate::datastructures::signature::Signature;
 
@@ -13,6 +15,35 @@ pub struct SlashInherent {
     pub justification2: Signature<MicroHeader>,
 }
 
+impl SlashInherent {
+    /// Checks that the same producer signed two different headers
+    /// on the same parent for the same block number and view.
+    pub fn verify(&self) -> bool {
+        let producer = &self.header1.digest.validator;
+        self.header1 != self.header2
+            && self.header1.parent_hash == self.header2.parent_hash
+            && self.header1.digest == self.header2.digest
+            && self.justification1.verify(producer, &self.header1)
+            && self.justification2.verify(producer, &self.header2)
+    }
+}
+
+impl ByteSize for SlashInherent {
+    fn byte_size(&self) -> usize {
+        self.header1.byte_size() + self.justification1.byte_size()
+            + self.header2.byte_size() + self.justification2.byte_size()
+    }
+}
+
+impl Encode for SlashInherent {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.header1.encode(buffer);
+        self.justification1.encode(buffer);
+        self.header2.encode(buffer);
+        self.justification2.encode(buffer);
+    }
+}
+
 impl VerificationTime for SlashInherent {
     fn verification_time(&self, timing: &Timing) -> Duration {
         self.justification1.verification_time(timing)
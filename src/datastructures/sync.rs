@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::datastructures::ByteSize;
+use crate::datastructures::block::MacroBlock;
+use crate::datastructures::block::MacroHeader;
+use crate::datastructures::hash::Hash;
+use crate::datastructures::pbft::PbftJustification;
+
+/// A request for blocks that a node is missing.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BlockRequest {
+    /// A single block, e.g., the unknown parent of a block on another fork.
+    Hash(Hash),
+    /// The blocks of the main chain with block numbers in the (inclusive) range.
+    Range(u32, u32),
+    /// All macro blocks after the given block number
+    /// and the micro blocks since the last macro block.
+    MacroBlocks(u32),
+    /// The headers of all macro blocks after the given block number.
+    /// Light nodes also subscribe to the headers of future macro blocks with this request.
+    MacroHeaders(u32),
+}
+
+impl ByteSize for BlockRequest {
+    fn byte_size(&self) -> usize {
+        // Request type and parameters.
+        1 + match self {
+            BlockRequest::Hash(hash) => hash.byte_size(),
+            BlockRequest::Range(_, _) => 4 + 4,
+            BlockRequest::MacroBlocks(_) | BlockRequest::MacroHeaders(_) => 4,
+        }
+    }
+}
+
+impl fmt::Display for BlockRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            BlockRequest::Hash(hash) => write!(f, "block {:?}", hash),
+            BlockRequest::Range(from, to) => write!(f, "blocks #{} to #{}", from, to),
+            BlockRequest::MacroBlocks(block_number) => write!(f, "macro blocks after #{}", block_number),
+            BlockRequest::MacroHeaders(block_number) => write!(f, "macro headers after #{}", block_number),
+        }
+    }
+}
+
+/// A macro block header together with the justification that finalised it.
+/// This is all a light node needs to follow the chain.
+#[derive(Clone, Debug)]
+pub struct FinalizedHeader {
+    pub header: MacroHeader,
+    pub justification: PbftJustification,
+}
+
+impl FinalizedHeader {
+    /// Returns the finalised header of a committed macro block, which is `None` for the genesis block.
+    pub fn from_block(block: &MacroBlock) -> Option<Self> {
+        Some(FinalizedHeader {
+            header: block.header.clone(),
+            justification: block.justification.clone()?,
+        })
+    }
+
+    pub fn hash(&self) -> Hash {
+        self.header.hash()
+    }
+}
+
+impl ByteSize for FinalizedHeader {
+    fn byte_size(&self) -> usize {
+        self.header.byte_size() + self.justification.byte_size()
+    }
+}
+
+impl fmt::Display for FinalizedHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "[#{}, view {}, type Macro header]", self.header.digest.block_number, self.header.digest.view_number)
+    }
+}
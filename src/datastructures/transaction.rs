@@ -2,12 +2,38 @@ use std::time::Duration;
 
 use crate::actors::Timing;
 use crate::actors::VerificationTime;
+use crate::datastructures::ByteSize;
+use crate::datastructures::Encode;
+use crate::datastructures::signature::PublicKey;
 
-#[derive(Clone, Debug)]
-pub struct Transaction {}
+/// A basic value transfer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Transaction {
+    pub sender: PublicKey,
+    pub recipient: PublicKey,
+    pub value: u64,
+    pub fee: u64,
+    pub validity_start_height: u32,
+}
 
 impl VerificationTime for Transaction {
     fn verification_time(&self, timing: &Timing) -> Duration {
         timing.verification
     }
-}
\ No newline at end of file
+}
+impl ByteSize for Transaction {
+    fn byte_size(&self) -> usize {
+        // Size of a basic transaction.
+        138
+    }
+}
+
+impl Encode for Transaction {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.sender.encode(buffer);
+        self.recipient.encode(buffer);
+        self.value.encode(buffer);
+        self.fee.encode(buffer);
+        self.validity_start_height.encode(buffer);
+    }
+}
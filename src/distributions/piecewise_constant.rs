@@ -83,13 +83,7 @@ pub enum PiecewiseConstantError {
 }
 
 impl Error for PiecewiseConstantError {
-    fn description(&self) -> &str {
-        match *self {
-            PiecewiseConstantError::WeightedError(ref e) => e.description(),
-            PiecewiseConstantError::InvalidSize => "Number of intervals must be number of weights + 1",
-        }
-    }
-    fn cause(&self) -> Option<&Error> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
             PiecewiseConstantError::WeightedError(ref e) => Some(e),
             PiecewiseConstantError::InvalidSize => None,
@@ -99,6 +93,9 @@ impl Error for PiecewiseConstantError {
 
 impl fmt::Display for PiecewiseConstantError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.description())
+        match *self {
+            PiecewiseConstantError::WeightedError(ref e) => write!(f, "{}", e),
+            PiecewiseConstantError::InvalidSize => write!(f, "Number of intervals must be number of weights + 1"),
+        }
     }
 }
\ No newline at end of file
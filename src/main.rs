@@ -13,17 +13,20 @@ use rand::rngs::OsRng;
 
 use simulator::Simulator;
 
-use crate::actors::Timing;
 use crate::cmdline::Options;
 use crate::logging::AlbatrossDispatch;
 use crate::protocol::ProtocolConfig;
 use crate::simulation::Event;
+use crate::simulation::hardware::HardwareProfiles;
 use crate::simulation::metrics::DefaultMetrics;
 use crate::simulation::network::AdvancedNetwork;
+use crate::simulation::scenario::Scenario;
 use crate::simulation::settings::ProtocolSettings;
+use crate::simulation::settings::ScenarioSettings;
 use crate::simulation::settings::Settings;
 use crate::simulation::settings::TimingSettings;
 use crate::simulation::SimulationConfig;
+use crate::simulation::synchrony::SynchronyNetwork;
 use crate::simulation::topology_helper::AdvancedTopologyHelper;
 
 pub mod datastructures;
@@ -64,52 +67,89 @@ fn main() {
 
 fn start_simulations(options: Options) {
     let mut settings = Settings::from_file(options.network_settings.unwrap()).unwrap();
-    let timing = Timing::from_settings(TimingSettings::from_file(options.timing_settings.unwrap()).unwrap());
+    let timing_settings = TimingSettings::from_file(options.timing_settings.unwrap()).unwrap();
     let protocol = ProtocolSettings::from_file(options.protocol_settings.unwrap()).unwrap();
     let topology = AdvancedTopologyHelper::from_settings(&mut settings).unwrap();
+    let hardware = HardwareProfiles::from_settings(&timing_settings, &topology).unwrap();
+    let scenario = Scenario::from_settings(&ScenarioSettings::from_file(options.scenario_settings.unwrap()).unwrap());
 
     // Sequentially run simulations.
     for &num_nodes in options.num_nodes.iter() {
+        let num_validators = match options.num_validators {
+            Some(num_validators) if num_validators == 0 || num_validators as usize > num_nodes => {
+                warn!("Cannot have {} validators among {} nodes, all nodes are validators.", num_validators, num_nodes);
+                num_nodes as u16
+            },
+            Some(num_validators) => num_validators,
+            None => num_nodes as u16,
+        };
+
         let mut iterations = Vec::with_capacity(options.iterations);
         for _ in 0..options.iterations {
             let simulation_config = SimulationConfig {
                 blocks: options.blocks,
+                num_light_nodes: options.num_light_nodes,
             };
             let protocol_config = ProtocolConfig {
+                variant: options.protocol.unwrap_or(protocol.protocol),
                 micro_block_timeout: options.micro_block_timeout.unwrap_or(Duration::from_micros(protocol.micro_block_timeout)),
                 macro_block_timeout: options.macro_block_timeout.unwrap_or(Duration::from_micros(protocol.macro_block_timeout)),
                 num_micro_blocks: options.num_micro_blocks.unwrap_or(protocol.num_micro_blocks),
-                num_validators: num_nodes as u16,
+                num_validators,
+                dissemination: options.dissemination.unwrap_or(protocol.dissemination),
+                aggregation: options.aggregation.unwrap_or(protocol.aggregation),
+                handel_period: Duration::from_micros(protocol.handel_period),
+                max_timestamp_drift: Duration::from_micros(protocol.max_timestamp_drift),
+                min_block_time: options.min_block_time.unwrap_or(Duration::from_micros(protocol.min_block_time)),
+                backoff: options.backoff.unwrap_or(protocol.backoff),
+                max_timeout: options.max_timeout.or(protocol.max_timeout.map(Duration::from_micros)),
             };
 
-            iterations.push(run_simulation(num_nodes, &topology, simulation_config, protocol_config, timing.clone()).map(|simulator| {
-                simulator.metrics().analyze()
+            let gst = scenario.synchrony().map(|synchrony| synchrony.gst);
+            iterations.push(run_simulation(num_nodes, &topology, &hardware, &scenario, simulation_config, protocol_config, options.producer_statistics).map(move |simulator| {
+                simulator.metrics().analyze();
+                if let Some(gst) = gst {
+                    simulator.metrics().analyze_synchrony(simulator.initial_time() + gst);
+                }
             }));
         }
         tokio::spawn(join_all(iterations).map(|_| ()));
     }
 }
 
-fn run_simulation(num_nodes: usize, topology: &AdvancedTopologyHelper, simulation_config: SimulationConfig, protocol_config: ProtocolConfig, timing: Timing) -> impl Future<Item=Simulator<AdvancedNetwork, DefaultMetrics>, Error=()> {
-    info!("Simulating {} parties Albatross!", num_nodes);
+fn run_simulation(num_nodes: usize, topology: &AdvancedTopologyHelper, hardware: &HardwareProfiles, scenario: &Scenario, simulation_config: SimulationConfig, protocol_config: ProtocolConfig, producer_statistics: bool) -> impl Future<Item=Simulator<SynchronyNetwork<AdvancedNetwork>, DefaultMetrics>, Error=()> {
+    info!("Simulating {} parties Albatross with {} validators!", num_nodes, protocol_config.num_validators);
     debug!("Simulation: {:#?}", simulation_config);
     debug!("Protocol: {:#?}", protocol_config);
-    debug!("Timing: {:#?}", timing);
+    for (name, timing) in hardware.names.iter().zip(hardware.timings.iter()) {
+        debug!("Timing of {}: {:#?}", name, timing);
+    }
 
-    let metrics = DefaultMetrics::default();
+    let num_light_nodes = simulation_config.num_light_nodes;
+    let metrics = DefaultMetrics {
+        light_nodes: (num_nodes..num_nodes + num_light_nodes).collect(),
+        num_nodes,
+        num_validators: protocol_config.num_validators as usize,
+        producer_statistics,
+        ..Default::default()
+    };
 
     info!("Creating network topology distributions.");
 
     let mut rng = OsRng::new().unwrap();
     info!("Setting up network.");
-    let network = AdvancedNetwork::new(num_nodes, &topology, simulation_config,
-                                       protocol_config, timing, &mut rng);
+    let view_change_threshold = protocol_config.two_third_threshold();
+    let network = AdvancedNetwork::new(num_nodes, topology, simulation_config,
+                                       protocol_config, hardware, scenario, &mut rng);
+    let genesis_block = network.genesis_block().clone();
+    let network = SynchronyNetwork::new(network, scenario.synchrony().cloned(), &genesis_block,
+                                        view_change_threshold, &mut rng);
 
     let mut simulator = Simulator::new(network, metrics);
 
     simulator.build();
 
-    for i in 0..num_nodes {
+    for i in 0..num_nodes + num_light_nodes {
         simulator.initial_event(i, Event::Init);
     }
 
@@ -0,0 +1,326 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::datastructures::block::Block;
+use crate::datastructures::block::BlockType;
+use crate::datastructures::hash::Hash;
+use crate::datastructures::sync::FinalizedHeader;
+use crate::protocol::BlockError;
+
+/// The outcome of storing a block.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PushResult {
+    /// The block extended the main chain.
+    Extended,
+    /// The block made us switch to another fork, reverting `depth` blocks of the main chain.
+    Rebranched(u32),
+    /// The block is stored on a fork that is not preferred over the main chain.
+    Forked,
+}
+
+/// Stores the main chain and a tree of all micro blocks since the last macro block.
+/// Micro blocks of epochs that were skipped by synchronising macro blocks are missing from the main chain.
+pub struct ChainStore {
+    chain: BTreeMap<u32, Block>,
+    // All blocks since (and including) the last macro block, on the main chain or on forks.
+    blocks: HashMap<Hash, Block>,
+}
+
+impl ChainStore {
+    pub fn new(genesis_block: Block) -> Self {
+        let mut blocks = HashMap::new();
+        blocks.insert(genesis_block.hash(), genesis_block.clone());
+        let mut chain = BTreeMap::new();
+        chain.insert(genesis_block.block_number(), genesis_block);
+        ChainStore {
+            chain,
+            blocks,
+        }
+    }
+
+    /// Returns the block at a given height on the main chain.
+    pub fn get(&self, block_number: u32) -> Option<&Block> {
+        self.chain.get(&block_number)
+    }
+
+    /// Returns the blocks of the main chain in the given range of block numbers.
+    pub fn range(&self, from: u32, to: u32) -> Vec<Block> {
+        self.chain.range(from..=to).map(|(_, block)| block.clone()).collect()
+    }
+
+    /// Returns all macro blocks on the main chain after the given block number,
+    /// followed by the micro blocks since the last macro block.
+    pub fn macro_blocks_since(&self, block_number: u32) -> Vec<Block> {
+        let last_macro_block = self.chain.values().rev()
+            .find(|block| block.block_type() == BlockType::Macro)
+            .map(|block| block.block_number())
+            .unwrap();
+        self.chain.range(block_number + 1..)
+            .filter(|(&number, block)| block.block_type() == BlockType::Macro || number > last_macro_block)
+            .map(|(_, block)| block.clone())
+            .collect()
+    }
+
+    /// Returns the finalised headers of all macro blocks on the main chain after the given block number.
+    pub fn macro_headers_since(&self, block_number: u32) -> Vec<FinalizedHeader> {
+        self.chain.range(block_number + 1..)
+            .filter_map(|(_, block)| match block {
+                Block::Macro(block) => FinalizedHeader::from_block(block),
+                Block::Micro(_) => None,
+            })
+            .collect()
+    }
+
+    /// Returns the head of the main chain.
+    pub fn head(&self) -> &Block {
+        self.chain.values().next_back().unwrap()
+    }
+
+    /// Returns a block since the last macro block by its hash, regardless of whether it is on the main chain.
+    pub fn get_by_hash(&self, hash: &Hash) -> Option<&Block> {
+        self.blocks.get(hash)
+    }
+
+    /// Checks whether a block has already been stored.
+    pub fn contains(&self, block: &Block) -> bool {
+        self.blocks.contains_key(&block.hash()) || self.is_on_main_chain(block)
+    }
+
+    /// Checks whether a block is part of the main chain.
+    pub fn is_on_main_chain(&self, block: &Block) -> bool {
+        self.get(block.block_number())
+            .map(|other| other.hash() == block.hash())
+            .unwrap_or(false)
+    }
+
+    /// Stores a block whose parent is known and applies the fork choice rule:
+    /// At the point two chains diverge, the block with the higher view number wins.
+    /// Blocks with equal view numbers stem from an equivocating producer and are resolved
+    /// in favour of the longer chain. If both chains are equally long, the block with the lower hash wins,
+    /// such that all nodes agree on one of the blocks without waiting for its successor.
+    /// Macro blocks are final and thus always win.
+    /// A macro block with an unknown parent skips the micro blocks of its epoch,
+    /// a micro block with an unknown parent is rejected.
+    pub fn push(&mut self, block: Block) -> Result<PushResult, BlockError> {
+        if !self.blocks.contains_key(block.parent_hash()) {
+            if block.block_type() != BlockType::Macro {
+                return Err(BlockError::UnknownParent);
+            }
+            return Ok(self.push_macro_block(block));
+        }
+        self.blocks.insert(block.hash(), block.clone());
+
+        // Collect the branch until we hit the main chain.
+        let mut branch = vec![block];
+        loop {
+            let parent = &self.blocks[branch.last().unwrap().parent_hash()];
+            if self.is_on_main_chain(parent) {
+                break;
+            }
+            branch.push(parent.clone());
+        }
+        let fork_point = branch.last().unwrap().block_number() - 1;
+
+        let rebranch = match self.get(fork_point + 1) {
+            _ if branch[0].block_type() == BlockType::Macro => true,
+            Some(main_block) => match branch.last().unwrap().view_number().cmp(&main_block.view_number()) {
+                Ordering::Greater => true,
+                Ordering::Equal => match branch[0].block_number().cmp(&self.head().block_number()) {
+                    Ordering::Greater => true,
+                    Ordering::Equal => branch.last().unwrap().hash() < main_block.hash(),
+                    Ordering::Less => false,
+                },
+                Ordering::Less => false,
+            },
+            None => true,
+        };
+        if !rebranch {
+            return Ok(PushResult::Forked);
+        }
+
+        let depth = self.head().block_number() - fork_point;
+        self.chain.split_off(&(fork_point + 1));
+        while let Some(block) = branch.pop() {
+            self.chain.insert(block.block_number(), block);
+        }
+
+        if self.head().block_type() == BlockType::Macro {
+            self.prune_forks();
+        }
+
+        Ok(Self::push_result(depth))
+    }
+
+    /// Appends a macro block without knowing the micro blocks of its epoch,
+    /// reverting any micro blocks we have after the last macro block.
+    fn push_macro_block(&mut self, block: Block) -> PushResult {
+        let last_macro_block = self.blocks.values()
+            .find(|block| block.block_type() == BlockType::Macro)
+            .map(|block| block.block_number())
+            .unwrap();
+
+        let depth = self.head().block_number() - last_macro_block;
+        self.chain.split_off(&(last_macro_block + 1));
+        self.chain.insert(block.block_number(), block);
+        self.prune_forks();
+
+        Self::push_result(depth)
+    }
+
+    /// Forks before a macro block are not of interest anymore.
+    fn prune_forks(&mut self) {
+        let head = self.head().clone();
+        self.blocks.clear();
+        self.blocks.insert(head.hash(), head);
+    }
+
+    fn push_result(depth: u32) -> PushResult {
+        if depth > 0 {
+            PushResult::Rebranched(depth)
+        } else {
+            PushResult::Extended
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datastructures::block::*;
+    use crate::datastructures::signature::KeyPair;
+
+    use super::*;
+
+    fn genesis_block() -> Block {
+        Block::Macro(MacroBlock::create_genesis_block(&(0..4).collect()))
+    }
+
+    /// A micro block on top of `parent`, blocks of the same view are told apart by their timestamp.
+    fn micro_block(parent: &Block, view_number: u16, timestamp: u64) -> Block {
+        let key_pair = KeyPair::from_id(0);
+        let extrinsics = MicroExtrinsics {
+            timestamp,
+            seed: key_pair.secret_key().sign(&parent.seed().hash()),
+            view_change_messages: None,
+            slash_inherents: Vec::new(),
+            transactions: Vec::new(),
+        };
+        let header = MicroHeader {
+            parent_hash: parent.hash(),
+            digest: MicroDigest {
+                validator: key_pair.public_key(),
+                block_number: parent.block_number() + 1,
+                view_number,
+            },
+            extrinsics_root: extrinsics.hash(),
+            state_root: Hash::default(),
+        };
+        Block::Micro(MicroBlock {
+            justification: key_pair.secret_key().sign(&header),
+            header,
+            extrinsics,
+        })
+    }
+
+    fn macro_block(parent_hash: Hash, block_number: u32) -> Block {
+        let mut block = MacroBlock::create_genesis_block(&(0..4).collect());
+        block.header.parent_hash = parent_hash;
+        block.header.digest.block_number = block_number;
+        Block::Macro(block)
+    }
+
+    /// A store with a main chain of micro blocks in view 0 on top of the genesis block.
+    fn chain(length: u32) -> (ChainStore, Vec<Block>) {
+        let genesis_block = genesis_block();
+        let mut store = ChainStore::new(genesis_block.clone());
+        let mut blocks = vec![genesis_block];
+        for _ in 0..length {
+            let block = micro_block(blocks.last().unwrap(), 0, 0);
+            assert_eq!(store.push(block.clone()).unwrap(), PushResult::Extended);
+            blocks.push(block);
+        }
+        (store, blocks)
+    }
+
+    #[test]
+    fn higher_view_wins() {
+        let (mut store, blocks) = chain(2);
+
+        // The shorter chain wins with the higher view at the fork point.
+        let view_change = micro_block(&blocks[0], 1, 1);
+        assert_eq!(store.push(view_change.clone()).unwrap(), PushResult::Rebranched(2));
+        assert_eq!(store.head().hash(), view_change.hash());
+
+        // The previous chain does not win back by growing longer.
+        let old = micro_block(&blocks[2], 0, 0);
+        assert_eq!(store.push(old).unwrap(), PushResult::Forked);
+        assert_eq!(store.head().hash(), view_change.hash());
+    }
+
+    #[test]
+    fn equal_view_longer_chain_wins() {
+        let (mut store, blocks) = chain(2);
+
+        let fork1 = micro_block(&blocks[0], 0, 1);
+        assert_eq!(store.push(fork1.clone()).unwrap(), PushResult::Forked);
+        assert_eq!(store.head().hash(), blocks[2].hash());
+
+        let fork2 = micro_block(&fork1, 0, 1);
+        let fork3 = micro_block(&fork2, 0, 1);
+        store.push(fork2).unwrap();
+        store.push(fork3.clone()).unwrap();
+        assert_eq!(store.head().hash(), fork3.hash());
+        assert!(!store.is_on_main_chain(&blocks[1]));
+    }
+
+    #[test]
+    fn equal_view_and_length_lower_hash_wins() {
+        let genesis_block = genesis_block();
+        let block1 = micro_block(&genesis_block, 0, 1);
+        let block2 = micro_block(&genesis_block, 0, 2);
+        let winner = if block1.hash() < block2.hash() { block1.hash() } else { block2.hash() };
+
+        // Nodes agree on the winner regardless of the order they received the blocks in.
+        for (first, second) in [(&block1, &block2), (&block2, &block1)] {
+            let mut store = ChainStore::new(genesis_block.clone());
+            assert_eq!(store.push(first.clone()).unwrap(), PushResult::Extended);
+            let expected = if second.hash() == winner { PushResult::Rebranched(1) } else { PushResult::Forked };
+            assert_eq!(store.push(second.clone()).unwrap(), expected);
+            assert_eq!(store.head().hash(), winner);
+        }
+    }
+
+    #[test]
+    fn macro_block_wins() {
+        let (mut store, blocks) = chain(1);
+        let view_change = micro_block(&blocks[0], 1, 1);
+        store.push(view_change.clone()).unwrap();
+        assert_eq!(store.head().hash(), view_change.hash());
+
+        // A macro block on the fork with the lower view is final.
+        let macro_block = macro_block(blocks[1].hash(), 2);
+        assert_eq!(store.push(macro_block.clone()).unwrap(), PushResult::Rebranched(1));
+        assert_eq!(store.head().hash(), macro_block.hash());
+        assert!(store.is_on_main_chain(&blocks[1]));
+
+        // Forks before the macro block are pruned.
+        assert!(store.get_by_hash(&view_change.hash()).is_none());
+        assert!(matches!(store.push(micro_block(&view_change, 1, 1)), Err(BlockError::UnknownParent)));
+    }
+
+    #[test]
+    fn macro_block_with_unknown_parent() {
+        let (mut store, blocks) = chain(2);
+        assert!(matches!(store.push(micro_block(&micro_block(&blocks[2], 0, 1), 0, 1)), Err(BlockError::UnknownParent)));
+
+        // The micro blocks of the epoch are skipped.
+        let macro_block = macro_block(Hash::default(), 5);
+        assert_eq!(store.push(macro_block.clone()).unwrap(), PushResult::Rebranched(2));
+        assert_eq!(store.head().hash(), macro_block.hash());
+        assert!(store.get(1).is_none() && store.get(2).is_none());
+        assert!(store.get_by_hash(&blocks[2].hash()).is_none());
+
+        // The next epoch builds on the macro block.
+        assert_eq!(store.push(micro_block(&macro_block, 0, 0)).unwrap(), PushResult::Extended);
+    }
+}
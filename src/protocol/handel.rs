@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::time::Duration;
+
+use simulator::Time;
+
+use crate::datastructures::pbft::AggregateProof;
+use crate::datastructures::pbft::HandelContribution;
+use crate::datastructures::pbft::PbftMessage;
+
+/// Returns the number of levels of the aggregation tree over the validators.
+pub fn num_levels(num_validators: usize) -> u8 {
+    num_validators.next_power_of_two().trailing_zeros() as u8
+}
+
+/// Returns the indices of the validators that the validator at `index` exchanges aggregates with at a level.
+/// At level `l`, these are the `2^(l-1)` validators of the sibling subtree.
+pub fn level_peers(index: usize, level: u8, num_validators: usize) -> Range<usize> {
+    let size = 1 << (level - 1);
+    let start = (index ^ size) & !(size - 1);
+    usize::min(start, num_validators)..usize::min(start + size, num_validators)
+}
+
+/// The aggregation of a single PBFT message.
+struct Aggregation {
+    // Our own vote and the time we cast it, which determines when levels are started.
+    own: Option<(AggregateProof<PbftMessage>, Time)>,
+    // The best aggregate received at each level, these are pairwise disjoint.
+    levels: Vec<Option<AggregateProof<PbftMessage>>>,
+    // The number of signers we last sent to each peer at each level.
+    sent: Vec<HashMap<usize, usize>>,
+    // Position in the round-robin over the peers of each level.
+    next_peer: Vec<usize>,
+}
+
+impl Aggregation {
+    fn new(num_levels: u8) -> Self {
+        let num_levels = usize::from(num_levels);
+        Aggregation {
+            own: None,
+            levels: vec![None; num_levels],
+            sent: vec![HashMap::new(); num_levels],
+            next_peer: vec![0; num_levels],
+        }
+    }
+
+    /// Returns our own vote aggregated with the best aggregates of all levels below the given one,
+    /// i.e., the signatures of our subtree at that level.
+    fn outgoing(&self, level: u8) -> Option<AggregateProof<PbftMessage>> {
+        let (own, _) = self.own.as_ref()?;
+        let mut proof = own.clone();
+        for aggregate in self.levels[..usize::from(level) - 1].iter().flatten() {
+            proof.aggregate(aggregate);
+        }
+        Some(proof)
+    }
+
+    fn num_signers(&self) -> usize {
+        self.own.iter().map(|(own, _)| own.num_signers()).sum::<usize>()
+            + self.levels.iter().flatten().map(AggregateProof::num_signers).sum::<usize>()
+    }
+}
+
+/// Aggregates prepare and commit messages along a binary tree over the validator indices in the style of Handel.
+/// At each level, validators exchange the aggregate of their own subtree with the sibling subtree.
+pub struct HandelState {
+    index: usize,
+    num_validators: usize,
+    num_levels: u8,
+    aggregations: HashMap<PbftMessage, Aggregation>,
+}
+
+impl HandelState {
+    pub fn new(index: usize, num_validators: usize) -> Self {
+        HandelState {
+            index,
+            num_validators,
+            num_levels: num_levels(num_validators),
+            aggregations: HashMap::new(),
+        }
+    }
+
+    /// Adds our own vote, which starts the dissemination of aggregates.
+    pub fn add_own(&mut self, message: PbftMessage, proof: AggregateProof<PbftMessage>, now: Time) {
+        let num_levels = self.num_levels;
+        self.aggregations.entry(message)
+            .or_insert_with(|| Aggregation::new(num_levels))
+            .own = Some((proof, now));
+    }
+
+    /// Checks that all signers of a contribution belong to the sibling subtree at its level.
+    pub fn is_valid_level(&self, contribution: &HandelContribution) -> bool {
+        if contribution.level == 0 || contribution.level > self.num_levels {
+            return false;
+        }
+
+        let peers = level_peers(self.index, contribution.level, self.num_validators);
+        let signers: HashSet<&u16> = contribution.proof.public_key_bitmap.iter().collect();
+        !signers.is_empty()
+            && signers.len() == contribution.proof.num_signers()
+            && signers.iter().all(|&&signer| peers.contains(&usize::from(signer)))
+    }
+
+    /// Stores a contribution if it has more signers than the best aggregate of its level.
+    /// Returns whether the contribution has been stored.
+    pub fn add_contribution(&mut self, contribution: HandelContribution) -> bool {
+        let num_levels = self.num_levels;
+        let aggregation = self.aggregations.entry(contribution.message)
+            .or_insert_with(|| Aggregation::new(num_levels));
+
+        let best = &mut aggregation.levels[usize::from(contribution.level) - 1];
+        if best.as_ref().map(AggregateProof::num_signers).unwrap_or(0) >= contribution.proof.num_signers() {
+            return false;
+        }
+        *best = Some(contribution.proof);
+        true
+    }
+
+    /// Checks whether we received the signatures of all validators of a level.
+    pub fn is_level_complete(&self, message: &PbftMessage, level: u8) -> bool {
+        let peers = level_peers(self.index, level, self.num_validators);
+        if peers.is_empty() {
+            return true;
+        }
+        self.aggregations.get(message)
+            .and_then(|aggregation| aggregation.levels[usize::from(level) - 1].as_ref())
+            .map(|best| best.num_signers() == peers.len())
+            .unwrap_or(false)
+    }
+
+    /// Returns the number of signatures we aggregated, including our own.
+    pub fn num_signers(&self, message: &PbftMessage) -> usize {
+        self.aggregations.get(message)
+            .map(Aggregation::num_signers)
+            .unwrap_or(0)
+    }
+
+    /// Checks whether we cast our vote and still aggregate the message.
+    pub fn is_active(&self, message: &PbftMessage) -> bool {
+        self.aggregations.get(message)
+            .map(|aggregation| aggregation.own.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Returns the contributions to be sent at time `now`, addressed by validator index.
+    /// At every started level, our aggregate is sent to the next peer that has not seen it yet.
+    /// Level `l` starts `l - 1` periods after our vote or as soon as all levels below are complete.
+    pub fn contributions(&mut self, message: &PbftMessage, now: Time, period: Duration) -> Vec<(usize, HandelContribution)> {
+        let lower_levels_complete: Vec<bool> = (1..=self.num_levels)
+            .map(|level| (1..level).all(|lower| self.is_level_complete(message, lower)))
+            .collect();
+
+        let aggregation = match self.aggregations.get_mut(message) {
+            Some(aggregation) => aggregation,
+            None => return Vec::new(),
+        };
+        let start = match aggregation.own {
+            Some((_, start)) => start,
+            None => return Vec::new(),
+        };
+
+        let mut contributions = Vec::new();
+        for level in 1..=self.num_levels {
+            let i = usize::from(level) - 1;
+            let started = now >= start + period * u32::from(level - 1);
+            if !started && !lower_levels_complete[i] {
+                break;
+            }
+
+            let proof = aggregation.outgoing(level).unwrap();
+            let peers: Vec<usize> = level_peers(self.index, level, self.num_validators).collect();
+            // Start at different peers to spread the load.
+            let offset = self.index + aggregation.next_peer[i];
+            let next = (0..peers.len())
+                .map(|j| peers[(offset + j) % peers.len()])
+                .position(|peer| aggregation.sent[i].get(&peer).cloned().unwrap_or(0) < proof.num_signers());
+
+            if let Some(j) = next {
+                let peer = peers[(offset + j) % peers.len()];
+                aggregation.next_peer[i] += j + 1;
+                aggregation.sent[i].insert(peer, proof.num_signers());
+                contributions.push((peer, HandelContribution {
+                    message: message.clone(),
+                    level,
+                    proof,
+                }));
+            }
+        }
+        contributions
+    }
+
+    pub fn reset(&mut self) {
+        self.aggregations.clear();
+    }
+}
@@ -1,355 +1,678 @@
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
 use std::fmt::Debug;
-
-use num_bigint::BigUint;
-use num_traits::ToPrimitive;
+use std::mem;
+use std::time::Duration;
 
 use simulator::Environment;
 use simulator::metrics::Metrics;
+use simulator::Time;
+use simulator::UniqueId;
 
 use crate::actors::Timing;
+use crate::actors::VerificationTime;
 use crate::datastructures::block::*;
 use crate::datastructures::hash::*;
 use crate::datastructures::pbft::*;
 use crate::datastructures::signature::*;
 use crate::datastructures::slashing::SlashInherent;
+use crate::datastructures::sync::BlockRequest;
+use crate::datastructures::sync::FinalizedHeader;
+use crate::protocol::Aggregation;
+use crate::protocol::Backoff;
 use crate::protocol::BlockError;
+use crate::protocol::chain_store::{ChainStore, PushResult};
+use crate::protocol::Dissemination;
+use crate::protocol::handel::HandelState;
 use crate::protocol::macro_block::{MacroBlockPhase, MacroBlockState};
+use crate::protocol::Protocol;
+use crate::protocol::producer_index;
 use crate::protocol::ProtocolConfig;
 use crate::protocol::ViewChangeState;
 use crate::simulation::Event;
 use crate::simulation::metrics::MetricsEventType;
 
+/// Weight of the moving average of block times, a new observation contributes 1/ADAPTIVE_WEIGHT.
+const ADAPTIVE_WEIGHT: u32 = 8;
+/// The adaptive micro block timeout is this multiple of the average block time,
+/// but at least `MIN_ADAPTIVE_TIMEOUT` to tolerate variance in propagation.
+const ADAPTIVE_TIMEOUT_FACTOR: u32 = 4;
+const MIN_ADAPTIVE_TIMEOUT: Duration = Duration::from_millis(500);
+
 pub struct HonestProtocol {
     protocol_config: ProtocolConfig,
     timing: Timing,
     view_change_state: ViewChangeState,
+    // View change messages for the micro blocks we accepted since the last macro block, by block number.
+    past_view_changes: HashMap<u32, ViewChangeState>,
     macro_block_state: MacroBlockState,
-    chain: Vec<Block>,
+    chain: ChainStore,
     key_pair: KeyPair,
+    clock_skew: i64, // ms
     validators: Vec<PublicKey>,
+    validator_ids: HashSet<UniqueId>,
+    // Full nodes that are not validators only verify and relay blocks.
+    validator_index: Option<usize>,
+    // Only set if votes are aggregated with Handel.
+    handel: Option<HandelState>,
 
     // Do not accept known blocks.
     known_blocks: HashSet<Hash>,
+    // Blocks that are scheduled for processing, but not verified yet, and the time their processing finishes.
+    processing_blocks: HashMap<Hash, Time>,
+    // Do not accept known proposals, re-proposals are identified by the view of their certificate.
+    known_proposals: HashSet<(Hash, Option<u16>)>,
+    // Do not relay known gossip messages.
+    known_gossip: HashSet<GossipId>,
+
+    // Blocks whose parent is unknown, indexed by the hash of their parent.
+    orphans: HashMap<Hash, Vec<Block>>,
+    // Only one block request is in flight at a time.
+    request_pending: Option<PendingRequest>,
+    // Identifies the timeouts of block requests.
+    num_requests: u32,
+    // The time at which we noticed that we fell behind.
+    sync_start: Option<Time>,
+    // Light peers only receive the headers of macro blocks.
+    light_peers: HashSet<UniqueId>,
+
+    // Moving average of the observed micro block times (excluding the minimum block time),
+    // used by the adaptive backoff.
+    average_block_time: Option<Duration>,
+    // The time at which our head last advanced.
+    last_head_change: Option<Time>,
+
+    // Proofs of equivocating producers in the current epoch, included in our next micro block.
+    pending_slashes: Vec<SlashInherent>,
+}
+
+/// A block request in flight.
+struct PendingRequest {
+    request: BlockRequest,
+    // Identifies the timeout of the last attempt.
+    id: u32,
+    // The peers asked so far.
+    asked: HashSet<UniqueId>,
+}
+
+/// Identifies a message that is gossiped over the validator overlay.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+enum GossipId {
+    ViewChange(ViewChange),
+    Proposal(Hash, Option<u16>),
+    Prepare(PbftProof),
+    Commit(PbftProof),
+}
+
+impl GossipId {
+    fn from_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::ViewChange(view_change) => Some(GossipId::ViewChange(view_change.clone())),
+            Event::BlockProposal(proposal, _, certificate) => Some(GossipId::Proposal(proposal.hash(), certificate.as_ref().map(|certificate| certificate.view_number))),
+            Event::BlockPrepare(proof) => Some(GossipId::Prepare(proof.clone())),
+            Event::BlockCommit(proof) => Some(GossipId::Commit(proof.clone())),
+            _ => None,
+        }
+    }
 }
 
 impl HonestProtocol {
     /// Create a protocol instance.
     pub fn new(protocol_config: ProtocolConfig, timing: Timing,
-               genesis_block: MacroBlock, key_pair: KeyPair) -> Self {
+               genesis_block: MacroBlock, key_pair: KeyPair, clock_skew: i64) -> Self {
+        let validators = genesis_block.header.digest.validators.clone();
+        let validator_index = validators.iter().position(|key| *key == key_pair.public_key());
+        let handel = match (protocol_config.aggregation, validator_index) {
+            (Aggregation::Handel, Some(index)) => Some(HandelState::new(index, validators.len())),
+            _ => None,
+        };
         HonestProtocol {
             protocol_config,
             timing,
             view_change_state: ViewChangeState::default(),
+            past_view_changes: HashMap::new(),
             macro_block_state: MacroBlockState::default(),
-            validators: genesis_block.header.digest.validators.clone(),
-            chain: vec![Block::Macro(genesis_block)],
+            validator_ids: validators.iter().map(|key| key.id() as UniqueId).collect(),
+            validators,
+            validator_index,
+            handel,
+            chain: ChainStore::new(Block::Macro(genesis_block)),
             key_pair,
+            clock_skew,
 
             known_blocks: HashSet::new(),
+            processing_blocks: HashMap::new(),
+            known_proposals: HashSet::new(),
+            known_gossip: HashSet::new(),
+
+            orphans: HashMap::new(),
+            request_pending: None,
+            num_requests: 0,
+            sync_start: None,
+            light_peers: HashSet::new(),
+
+            average_block_time: None,
+            last_head_change: None,
+
+            pending_slashes: Vec::new(),
         }
     }
 
-    /// Returns the next block number.
-    pub fn current_block_number(&self) -> u32 {
-        self.chain.len() as u32 - 1
+    fn is_validator(&self) -> bool {
+        self.validator_index.is_some()
     }
 
     /// Returns the next block number.
     fn next_block_number(&self) -> u32 {
-        self.chain.len() as u32
+        self.current_block_number() + 1
     }
 
     /// Last macro block number.
     fn last_macro_block(&self) -> u32 {
-        let current_block_number = self.chain.len() as u32 - 1;
+        let current_block_number = self.current_block_number();
         current_block_number - (current_block_number % (self.protocol_config.num_micro_blocks + 1 /*macro block*/))
     }
 
+    /// Number of the next macro block.
+    fn next_macro_block(&self) -> u32 {
+        self.last_macro_block() + self.protocol_config.num_micro_blocks + 1 /*macro block*/
+    }
+
+    /// Hash of the last macro block.
+    fn last_macro_block_hash(&self) -> Hash {
+        self.chain.get(self.last_macro_block()).map(|block| block.hash()).unwrap()
+    }
+
     /// Block type at a given number.
     fn block_type_at(&self, block_number: u32) -> BlockType {
-        if block_number % (self.protocol_config.num_micro_blocks + 1 /*macro block*/) == 0 {
+        if block_number.is_multiple_of(self.protocol_config.num_micro_blocks + 1 /*macro block*/) {
             BlockType::Macro
         } else {
             BlockType::Micro
         }
     }
 
-    /// Stores a block in the chain without any additional verifications.
-    /// This method only has some basic assertions to ensure correctness of the implementation.
-    fn store_block(&mut self, block: Block) {
+    /// Returns the local time in milliseconds since the genesis block,
+    /// as seen by our (potentially skewed) clock.
+    fn local_timestamp(&self, env: &Environment<Event, MetricsEventType>) -> u64 {
+        let elapsed = (env.time() - env.initial_time()).as_millis() as i64;
+        i64::max(elapsed + self.clock_skew, 0) as u64
+    }
+
+    /// Stores a block in the chain store without any additional verifications.
+    /// Resets the state if the block changed our head and processes orphans building on the block.
+    fn store_block(&mut self, block: Block, env: &mut Environment<Event, MetricsEventType>) -> Result<PushResult, BlockError> {
+        let hash = block.hash();
         let block_number = block.block_number();
-        // Do not allow orphan blocks.
-        assert!(block_number <= self.chain.len() as u32);
+        let block_type = block.block_type();
+        let next_block_number = self.next_block_number();
+        let next_macro_block = self.next_macro_block();
+        let result = self.chain.push(block.clone())?;
+        self.known_blocks.insert(hash.clone()); // Also store known block if we produced it.
+        if let Block::Macro(ref macro_block) = block {
+            // Notify light peers about the finalised macro block.
+            if let Some(header) = FinalizedHeader::from_block(macro_block) {
+                for &peer in self.light_peers.iter() {
+                    env.send_to(peer, Event::MacroHeaders(vec![header.clone()]));
+                }
+            }
+
+            // Gossip messages, proposals, orphans and slashes from before the macro block are not of interest anymore.
+            self.pending_slashes.clear();
+            self.known_gossip.clear();
+            self.known_proposals.clear();
+            self.orphans.retain(|_, blocks| {
+                blocks.retain(|block| block.block_number() > block_number);
+                !blocks.is_empty()
+            });
+        }
+
+        if let Block::Micro(ref micro_block) = block {
+            // Slashes do not need to be included twice.
+            self.pending_slashes.retain(|slash| !micro_block.extrinsics.slash_inherents.iter()
+                .any(|included| included.header1.digest == slash.header1.digest));
+        }
+
+        if let Some(children) = self.orphans.remove(&hash) {
+            for child in children {
+                let processing_time = env.time() + self.timing.block_processing_time(&child);
+                self.schedule_processing(child, processing_time, env);
+            }
+        } else if self.orphans.is_empty() && self.request_pending.is_none() {
+            if let Some(sync_start) = self.sync_start.take() {
+                env.note_event(&MetricsEventType::CaughtUp {
+                    own: env.own_id(),
+                    latency: env.time() - sync_start,
+                }, env.time());
+            }
+        }
 
-        // Revert chain until len == block_number
-        while block_number < self.chain.len() as u32 {
-            let block = self.chain.pop();
-            // Macro blocks cannot be forked.
-            assert_ne!(block.map(|b| b.block_type()), Some(BlockType::Macro));
+        if let PushResult::Rebranched(depth) = result {
+            env.note_event(&MetricsEventType::Rebranch {
+                own: env.own_id(),
+                depth,
+            }, env.time());
         }
 
-        self.known_blocks.insert(block.hash()); // Also store known block if we produced it.
-        self.chain.push(block);
+        if result != PushResult::Forked {
+            env.note_event(&MetricsEventType::HeadChanged {
+                own: env.own_id(),
+                hash: hash.clone(),
+            }, env.time());
+
+            if block_type == BlockType::Micro {
+                self.observe_block_time(env.time());
+            }
+            self.last_head_change = Some(env.time());
 
-        self.view_change_state.reset();
-        self.macro_block_state.reset();
+            // Others might still give up on the block we accepted, so we keep its view change messages.
+            let view_change_state = mem::take(&mut self.view_change_state);
+            if block_number == next_block_number {
+                self.past_view_changes.insert(block_number, view_change_state);
+            }
+            let (last_macro_block, current_block_number) = (self.last_macro_block(), self.current_block_number());
+            self.past_view_changes.retain(|&number, _| number > last_macro_block && number <= current_block_number);
+            // Micro blocks switching forks during PBFT do not affect the macro block, nor our lock on it.
+            if self.next_macro_block() != next_macro_block {
+                self.macro_block_state.reset();
+                if let Some(ref mut handel) = self.handel {
+                    handel.reset();
+                }
+            }
+        }
+        Ok(result)
     }
 
     /// Prepare protocol for next block:
     /// Check if we are the next block producer.
-    /// If so, produce a block, otherwise set a timeout.
-    pub fn prepare_next_block(&mut self, env: &mut Environment<Event, MetricsEventType>) {
+    /// If so, produce a block. In any case, set a timeout.
+    /// Nodes that are not validators neither produce blocks nor start view changes.
+    fn prepare_next_block(&mut self, env: &mut Environment<Event, MetricsEventType>) {
+        if !self.is_validator() {
+            return;
+        }
+
         let next_producer = self.get_producer_at(self.next_block_number(), self.view_change_state.view_number);
         if next_producer == self.key_pair.public_key() {
-            self.produce_block(env);
-        } else {
-            // Set a timeout.
-            match self.block_type_at(self.next_block_number()) {
-                BlockType::Micro => {
-                    let delay = self.protocol_config.micro_block_timeout * (self.view_change_state.view_number + 1).into();
-                    env.schedule_self(Event::MicroBlockTimeout(self.next_block_number(), self.view_change_state.view_number), env.time() + delay);
-                },
-                BlockType::Macro => {
-                    let delay = self.protocol_config.macro_block_timeout * (self.view_change_state.view_number + 1).into();
-                    env.schedule_self(Event::MacroBlockTimeout(self.next_block_number(), self.view_change_state.view_number, self.macro_block_state.phase), env.time() + delay);
-                },
+            let delay = self.pacing_delay(env);
+            if delay == Duration::default() {
+                self.produce_block(env);
+            } else {
+                env.schedule_self(Event::BlockProductionDue(self.next_block_number(), self.view_change_state.view_number), env.time() + delay);
             }
         }
+        self.schedule_timeout(env);
     }
 
-    /// A block has been received, simulate processing.
-    pub fn received_block(&mut self, block: Block, env: &mut Environment<Event, MetricsEventType>) {
-        // Check whether we already received this block.
-        let hash = block.hash();
-        if self.known_blocks.contains(&hash) {
-            return;
-        }
-        self.known_blocks.insert(hash);
-
-        let processing_time = env.time() + self.timing.block_processing_time(&block);
-        env.schedule_self(Event::BlockProcessed(block), processing_time);
+    /// Returns how long it takes on our clock until the minimum block time since our head has passed.
+    fn pacing_delay(&self, env: &Environment<Event, MetricsEventType>) -> Duration {
+        let earliest = self.chain.head().timestamp() + self.protocol_config.min_block_time.as_millis() as u64;
+        Duration::from_millis(earliest.saturating_sub(self.local_timestamp(env)))
     }
 
-    /// A block has been processed, ensure its validity.
-    /// If it is invalid, ignore it.
-    /// If it is valid, store block and reset state.
-    pub fn processed_block(&mut self, block: Block, env: &mut Environment<Event, MetricsEventType>) {
-        // We verify the block.
-        let result = self.verify_block(&block);
-
-        // TODO: Handle slashing (we currently do not store the headers of known blocks).
+    /// Updates the moving average of micro block times with a block accepted at `now`.
+    fn observe_block_time(&mut self, now: Time) {
+        let last_head_change = match self.last_head_change {
+            Some(last_head_change) => last_head_change,
+            None => return,
+        };
+        let block_time = (now - last_head_change).saturating_sub(self.protocol_config.min_block_time);
+        self.average_block_time = Some(match self.average_block_time {
+            Some(average) => (average * (ADAPTIVE_WEIGHT - 1) + block_time) / ADAPTIVE_WEIGHT,
+            None => block_time,
+        });
+    }
 
-        if let Err(ref e) = result {
-            warn!("Got invalid block, reason {:?}", e);
+    /// Returns the micro block timeout in view 0.
+    /// The adaptive backoff derives it from the observed block times once there are any.
+    fn micro_block_timeout(&self) -> Duration {
+        match (self.protocol_config.backoff, self.average_block_time) {
+            (Backoff::Adaptive, Some(average)) => Duration::max(average * ADAPTIVE_TIMEOUT_FACTOR, MIN_ADAPTIVE_TIMEOUT),
+            _ => self.protocol_config.micro_block_timeout,
         }
+    }
 
-        if result.is_ok() {
-            self.store_block(block.clone());
-
-            // Relay block.
-            self.relay(Event::Block(block), env);
-
-            self.prepare_next_block(env);
-        } else {
-            // Ignore block.
+    /// Sets a timeout for the next block in the current view.
+    /// Timeouts start once the minimum block time has passed.
+    fn schedule_timeout(&self, env: &mut Environment<Event, MetricsEventType>) {
+        let view_number = self.view_change_state.view_number;
+        match self.block_type_at(self.next_block_number()) {
+            BlockType::Micro => {
+                let delay = self.pacing_delay(env) + self.protocol_config.timeout(self.micro_block_timeout(), view_number);
+                env.schedule_self(Event::MicroBlockTimeout(self.next_block_number(), self.view_change_state.view_number), env.time() + delay);
+            },
+            BlockType::Macro => {
+                let delay = self.pacing_delay(env) + self.protocol_config.timeout(self.protocol_config.macro_block_timeout, view_number);
+                env.schedule_self(Event::MacroBlockTimeout(self.next_block_number(), self.view_change_state.view_number, self.macro_block_state.phase), env.time() + delay);
+            },
         }
     }
 
-    /// Called when a timeout has been triggered.
-    /// Check whether a corresponding (valid) block has been received in the meantime.
-    /// If not, prepare and send out view change message.
-    pub fn handle_timeout(&mut self, block_number: u32, view_number: u16, env: &mut Environment<Event, MetricsEventType>) {
-        // Check whether timeout was triggered and no new block has been accepted in the meanwhile.
-        if self.next_block_number() == block_number && self.view_change_state.view_number == view_number {
-            // Send and process view change message.
-            let view_change = ViewChange::new(block_number, view_number + 1, &self.key_pair.secret_key());
-            self.multicast_to_validators(Event::ViewChange(view_change.clone()), env);
-
-            // Handle own message exactly like others.
-            self.handle_view_change(view_change, env);
-        }
+    /// Verifies and stores a block once it has been processed at the given time.
+    fn schedule_processing(&mut self, block: Block, time: Time, env: &mut Environment<Event, MetricsEventType>) {
+        self.processing_blocks.insert(block.hash(), time);
+        env.schedule_self(Event::BlockProcessed(block), time);
     }
 
-    /// Called when a view change message has been received.
-    /// First check validity, then add message.
-    /// If we received enough view change messages for this view change number,
-    /// stop accepting blocks for this number and move on.
-    /// In this case, also check for next block producer or start timeout.
-    pub fn handle_view_change(&mut self, view_change: ViewChange, env: &mut Environment<Event, MetricsEventType>) {
-        // Validate view change message:
-        // Should be for current block and have a valid signature.
-        if view_change.internals.block_number != self.next_block_number()
-            || !view_change.verify() {
+    /// Buffers a block with an unknown parent and requests the missing blocks:
+    /// Nodes that are at least an epoch behind synchronise from macro block to macro block,
+    /// others request the missing blocks of the current epoch or the unknown parent itself.
+    fn add_orphan(&mut self, block: Block, from: UniqueId, env: &mut Environment<Event, MetricsEventType>) {
+        let block_number = block.block_number();
+        let parent_hash = block.parent_hash().clone();
+        self.orphans.entry(parent_hash.clone()).or_default().push(block);
+
+        // The parent might still be processed (unless its processing was lost, e.g. because we crashed).
+        // If we received, but rejected it before, we request it again.
+        if self.request_pending.is_some() || self.chain.get_by_hash(&parent_hash).is_some()
+            || self.processing_blocks.get(&parent_hash).is_some_and(|&time| time >= env.time()) {
             return;
         }
 
-        self.view_change_state.add_message(view_change);
+        let request = if block_number > self.next_macro_block() {
+            BlockRequest::MacroBlocks(self.last_macro_block())
+        } else if block_number > self.next_block_number() {
+            BlockRequest::Range(self.next_block_number(), block_number - 1)
+        } else {
+            BlockRequest::Hash(parent_hash)
+        };
 
-        // When 2f + 1 view change messages have been received,
-        // commit to not accepting a block from the previous owner anymore.
-        if self.view_change_state.num_messages(self.view_change_state.view_number + 1) > self.protocol_config.two_third_threshold() {
-            self.view_change_state.view_number += 1;
+        self.request_pending = Some(PendingRequest {
+            request,
+            id: 0,
+            asked: HashSet::new(),
+        });
+        self.sync_start.get_or_insert(env.time());
+        self.send_request(from, env);
+    }
+
+    /// Sends the pending block request to a peer and gives it until the micro block timeout to answer.
+    fn send_request(&mut self, peer: UniqueId, env: &mut Environment<Event, MetricsEventType>) {
+        let pending = match self.request_pending {
+            Some(ref mut pending) => pending,
+            None => return,
+        };
 
-            let delay = self.protocol_config.micro_block_timeout * (self.view_change_state.view_number + 1).into();
-            env.schedule_self(Event::MicroBlockTimeout(self.next_block_number(), self.view_change_state.view_number), env.time() + delay);
+        self.num_requests += 1;
+        pending.id = self.num_requests;
+        pending.asked.insert(peer);
+        env.send_to(peer, Event::BlockRequest(pending.request.clone()));
+        env.schedule_self(Event::BlockRequestTimeout(pending.id), env.time() + self.protocol_config.micro_block_timeout);
+    }
 
-            // Also always make sure to reset the macro block state.
-            self.macro_block_state.reset();
+    /// Sends the pending block request to a full peer we have not asked yet.
+    /// Once all of them have been asked, we start over.
+    fn retry_request(&mut self, env: &mut Environment<Event, MetricsEventType>) {
+        let peers: Vec<UniqueId> = env.peers().iter()
+            .filter(|peer| !self.light_peers.contains(peer))
+            .cloned()
+            .collect();
+        let pending = match self.request_pending {
+            Some(ref mut pending) => pending,
+            None => return,
+        };
 
-            self.prepare_next_block(env);
+        if peers.iter().all(|peer| pending.asked.contains(peer)) {
+            pending.asked.clear();
         }
-    }
 
-    /// Handles a macro block proposal.
-    pub fn handle_macro_block_proposal(&mut self, proposal: MacroBlock, signature: Signature<MacroHeader>, env: &mut Environment<Event, MetricsEventType>) {
-        // Check whether we already received this block.
-        let hash = proposal.hash();
-        if self.known_blocks.contains(&hash) {
-            return;
+        if let Some(&peer) = peers.iter().find(|peer| !pending.asked.contains(peer)) {
+            debug!("Requesting {} from {} instead", pending.request, peer);
+            self.send_request(peer, env);
         }
-        self.known_blocks.insert(hash);
+    }
 
-        let processing_time = env.time() + self.timing.proposal_processing_time(&proposal);
-        env.schedule_self(Event::ProposalProcessed(proposal, signature), processing_time);
+    /// Sends and processes a view change message for the next block.
+    /// If an earlier view change has not succeeded, we move on to the view after it.
+    fn start_view_change(&mut self, env: &mut Environment<Event, MetricsEventType>) {
+        let new_view_number = self.view_change_state.min_view_number() + 1;
+        self.send_view_change(new_view_number, env);
     }
 
-    /// A macro block proposal has been processed.
-    pub fn processed_proposal(&mut self, proposal: MacroBlock, signature: Signature<MacroHeader>, env: &mut Environment<Event, MetricsEventType>) {
-        // No duplicate proposal processing.
-        if self.macro_block_state.proposal.is_some() {
-            return;
-        }
+    /// Sends and processes a view change message to the given view for the next block.
+    /// Sets a timeout after which we give up on the view change.
+    fn send_view_change(&mut self, new_view_number: u16, env: &mut Environment<Event, MetricsEventType>) {
+        self.view_change_state.sent_view_number = new_view_number;
+        let base = match self.block_type_at(self.next_block_number()) {
+            BlockType::Micro => self.micro_block_timeout(),
+            BlockType::Macro => self.protocol_config.macro_block_timeout,
+        };
+        let timeout = self.protocol_config.timeout(base, new_view_number);
+        env.schedule_self(Event::ViewChangeTimeout(self.next_block_number(), new_view_number), env.time() + timeout);
 
-        // We verify the proposal first.
-        let mut result = self.verify_macro_block(&proposal, true);
+        let view_change = ViewChange::new(self.next_block_number(), new_view_number,
+                                          self.macro_block_state.locked.clone(), &self.key_pair.secret_key());
+        self.multicast_to_validators(Event::ViewChange(view_change.clone()), env);
 
-        // Check block producer.
-        let public_key = self.get_producer_at(proposal.header.digest.block_number, proposal.header.digest.view_number);
-        if !signature.verify(&public_key,
-                             &proposal.header) {
-            result = Err(BlockError::InvalidBlockProducer);
-        }
+        // Handle own message exactly like others.
+        self.handle_view_change(view_change, env);
+    }
 
-        if let Err(ref e) = result {
-            warn!("Got invalid block proposal, reason {:?}", e);
+    /// Handles a view change message for a micro block we already accepted.
+    /// If more than f validators gave up on the block, at least one honest validator did not accept it in time.
+    /// We support their view change then, so that the validators do not stay split between both chains.
+    fn handle_past_view_change(&mut self, view_change: ViewChange, env: &mut Environment<Event, MetricsEventType>) {
+        let block_number = view_change.internals.block_number;
+        let new_view_number = view_change.internals.new_view_number;
+        let view_number = match self.chain.get(block_number) {
+            Some(Block::Micro(block)) => block.header.digest.view_number,
+            _ => return,
+        };
+        let view_change_state = match self.past_view_changes.get_mut(&block_number) {
+            Some(view_change_state) => view_change_state,
+            None => return,
+        };
+        if new_view_number <= u16::max(view_number, view_change_state.sent_view_number)
+            || view_change.locked.is_some() || !view_change.verify() {
+            return;
         }
 
-        if result.is_ok() {
-            // Update state.
-            self.macro_block_state.proposal = Some(proposal.clone());
-            self.macro_block_state.phase = MacroBlockPhase::PROPOSED;
-
-            let hash = proposal.header.hash();
-            // Relay block.
-            self.relay(Event::BlockProposal(proposal, signature), env);
-
-            // Send and process prepare message.
-            // FIXME: Currently prepare/commit signatures are identical in the simulation.
-            let prepare = PbftProof::new(&hash, &self.key_pair.secret_key());
-            self.multicast_to_validators(Event::BlockPrepare(prepare.clone()), env);
-
-            self.handle_prepare(prepare, env);
-        } else {
-            // Ignore block.
+        view_change_state.add_message(view_change);
+        if view_change_state.num_messages(new_view_number) > self.protocol_config.max_malicious() {
+            view_change_state.sent_view_number = new_view_number;
+            let view_change = ViewChange::new(block_number, new_view_number, None, &self.key_pair.secret_key());
+            self.multicast_to_validators(Event::ViewChange(view_change), env);
         }
     }
 
-    /// Handles an incoming prepare message.
-    pub fn handle_prepare(&mut self, prepare: PbftProof, env: &mut Environment<Event, MetricsEventType>) {
-        let hash;
-        if let Some(ref proposal) = self.macro_block_state.proposal {
-            // Verify prepare.
-            hash = proposal.header.hash();
-            if !prepare.verify(&hash) {
-                return;
-            }
-        } else {
-            // Ignore if we cannot verify.
-            return;
+    /// Checks whether a proposal is compatible with the proposal we are locked on.
+    /// Proposals from earlier views are only accepted with a prepare certificate,
+    /// and once locked, we only accept our locked proposal or one that was prepared in a later view.
+    fn verify_lock(&self, proposal: &MacroBlock, certificate: Option<&PrepareCertificate>) -> Result<(), BlockError> {
+        let hash = proposal.header.hash();
+        match certificate {
+            None => {
+                if proposal.header.digest.view_number < self.view_change_state.view_number {
+                    return Err(BlockError::OldViewChangeNumber);
+                }
+            },
+            Some(certificate) => {
+                if certificate.view_number > self.view_change_state.view_number
+                    || !certificate.verify(&self.validators, &hash, self.protocol_config.two_third_threshold()) {
+                    return Err(BlockError::InvalidPrepareCertificate);
+                }
+            },
         }
 
-        if self.macro_block_state.has_prepare(&prepare) {
-            return;
+        if let Some(ref locked) = self.macro_block_state.locked {
+            let unlocked = certificate
+                .map(|certificate| certificate.view_number > locked.certificate.view_number)
+                .unwrap_or(false);
+            if locked.proposal.header.hash() != hash && !unlocked {
+                return Err(BlockError::LockedOnOtherProposal);
+            }
         }
 
-        self.macro_block_state.add_prepare(prepare);
+        Ok(())
+    }
 
-        // When 2f + 1 prepare messages have been received, commit to proposal.
-        if self.macro_block_state.num_prepares() > self.protocol_config.two_third_threshold() {
+    /// When 2f + 1 prepare messages have been received, lock and commit to proposal.
+    /// Only send our commit once.
+    fn check_prepared(&mut self, env: &mut Environment<Event, MetricsEventType>) {
+        let hash = match self.macro_block_state.proposal_hash() {
+            Some(hash) => hash,
+            None => return,
+        };
+
+        let view_number = self.view_change_state.view_number;
+        if self.macro_block_state.phase == MacroBlockPhase::PROPOSED
+            && self.macro_block_state.num_prepares(&hash, view_number) > self.protocol_config.two_third_threshold() {
             self.macro_block_state.phase = MacroBlockPhase::PREPARED;
 
-            // Send and process prepare message.
-            // FIXME: Currently prepare/commit signatures are identical in the simulation.
-            let commit = PbftProof::new(&hash, &self.key_pair.secret_key());
-            self.multicast_to_validators(Event::BlockCommit(commit.clone()), env);
+            let (proposal, signature) = self.macro_block_state.proposal.clone().unwrap();
+            self.macro_block_state.locked = Some(Box::new(LockedProposal {
+                proposal,
+                signature,
+                certificate: PrepareCertificate {
+                    view_number,
+                    prepare: AggregateProof::create(self.macro_block_state.get_prepares(&hash, view_number).unwrap(), &self.validators),
+                },
+            }));
+
+            // Send and process commit message.
+            let commit = PbftProof::commit(&hash, &self.key_pair.secret_key());
+            self.send_vote(commit.clone(), Event::BlockCommit(commit.clone()), env);
 
             self.handle_commit(commit, env);
+            self.check_committed(env);
         }
     }
 
-    /// Handles an incoming prepare message.
-    pub fn handle_commit(&mut self, commit: PbftProof, env: &mut Environment<Event, MetricsEventType>) {
-        let hash;
-        if let Some(ref proposal) = self.macro_block_state.proposal {
-            // Verify prepare.
-            hash = proposal.header.hash();
-            if !commit.verify(&hash) {
+    /// Sends our prepare or commit message to all validators,
+    /// or starts aggregating it with Handel.
+    fn send_vote(&mut self, vote: PbftProof, event: Event, env: &mut Environment<Event, MetricsEventType>) {
+        let index = self.validator_index.unwrap();
+        let handel = match self.handel {
+            Some(ref mut handel) => handel,
+            None => {
+                self.multicast_to_validators(event, env);
                 return;
-            }
-        } else {
-            // Ignore if we cannot verify.
-            return;
-        }
+            },
+        };
 
-        if self.macro_block_state.has_commit(&commit) {
-            return;
-        }
+        let message = vote.signature.message().clone();
+        handel.add_own(message.clone(), AggregateProof::from_proof(&vote, index as u16), env.time());
+        self.send_contributions(&message, env);
+        env.schedule_self(Event::HandelTimeout(message), env.time() + self.protocol_config.handel_period);
+    }
 
-        self.macro_block_state.add_commit(commit);
+    /// Sends our aggregates for the due levels of Handel.
+    fn send_contributions(&mut self, message: &PbftMessage, env: &mut Environment<Event, MetricsEventType>) {
+        let handel = match self.handel {
+            Some(ref mut handel) => handel,
+            None => return,
+        };
 
-        // When 2f + 1 prepare messages have been received, commit to proposal.
-        if self.macro_block_state.num_commits() > self.protocol_config.two_third_threshold() {
-            self.macro_block_state.phase = MacroBlockPhase::COMMITTED;
+        for (index, contribution) in handel.contributions(message, env.time(), self.protocol_config.handel_period) {
+            env.send_to(self.validators[index].id() as UniqueId, Event::HandelContribution(contribution));
+        }
+    }
 
-            // Block proposal accepted, build it and relay it.
-            let mut block = self.macro_block_state.proposal.take().unwrap();
-            block.justification = Some(PbftJustification {
-                prepare: AggregateProof::create(&self.macro_block_state.prepares, &self.validators),
-                commit: AggregateProof::create(&self.macro_block_state.commits, &self.validators),
-            });
+    /// When 2f + 1 commit messages have been received, accept the proposal.
+    fn check_committed(&mut self, env: &mut Environment<Event, MetricsEventType>) {
+        let hash = match self.macro_block_state.proposal_hash() {
+            Some(hash) => hash,
+            None => return,
+        };
 
-            let block = Block::Macro(block);
+        if self.macro_block_state.num_commits(&hash) > self.protocol_config.two_third_threshold() {
+            // The prepares are taken from the view we locked on the proposal in, if any.
+            // Otherwise, the justification must wait for the prepares of the current view.
+            let (view_number, prepare) = match self.macro_block_state.locked {
+                Some(ref locked) if locked.proposal.header.hash() == hash => (locked.certificate.view_number, locked.certificate.prepare.clone()),
+                _ => {
+                    let view_number = self.view_change_state.view_number;
+                    if self.macro_block_state.num_prepares(&hash, view_number) <= self.protocol_config.two_third_threshold() {
+                        return;
+                    }
+                    (view_number, AggregateProof::create(self.macro_block_state.get_prepares(&hash, view_number).unwrap(), &self.validators))
+                },
+            };
 
-            self.store_block(block.clone());
-            // We accepted the block.
-            self.macro_block_state.proposal = None;
+            // Block proposal accepted, build it.
+            self.macro_block_state.phase = MacroBlockPhase::COMMITTED;
+            let (mut block, _) = self.macro_block_state.proposal.take().unwrap();
+            let justification = PbftJustification {
+                view_number,
+                prepare,
+                commit: AggregateProof::create(&self.macro_block_state.commits[&hash], &self.validators),
+            };
+            let aggregation_time = self.timing.justification_time(&justification);
+            block.justification = Some(justification);
 
-            // Relay block.
-            self.relay(Event::Block(block.clone()), env);
+            let block = Block::Macro(block);
 
-            env.note_event(&MetricsEventType::MacroBlockAccepted(block), env.time());
+            env.note_event(&MetricsEventType::MacroBlockAccepted(Box::new(block.clone())), env.time());
 
-            self.prepare_next_block(env);
+            // Store and relay the block once the votes have been aggregated.
+            self.schedule_processing(block, env.time() + aggregation_time, env);
         }
     }
 
     /// Verifies a block of any type.
-    fn verify_block(&self, block: &Block) -> Result<(), BlockError> {
+    /// `now` is the local timestamp used to check the block's timestamp.
+    fn verify_block(&self, block: &Block, now: u64) -> Result<(), BlockError> {
         match block {
-            Block::Micro(ref micro_block) => self.verify_micro_block(micro_block),
-            Block::Macro(ref macro_block) => self.verify_macro_block(macro_block, false),
+            Block::Micro(ref micro_block) => self.verify_micro_block(micro_block, now),
+            Block::Macro(ref macro_block) => self.verify_macro_block(macro_block, false, now),
+        }
+    }
+
+    /// Verifies that a timestamp is neither too far in the future
+    /// nor before the timestamp of the block's parent.
+    fn verify_timestamp(&self, timestamp: u64, parent: &Block, now: u64) -> Result<(), BlockError> {
+        if timestamp > now + self.protocol_config.max_timestamp_drift.as_millis() as u64 {
+            return Err(BlockError::FutureTimestamp);
+        }
+
+        if timestamp < parent.timestamp() {
+            return Err(BlockError::OldTimestamp);
+        }
+
+        if timestamp < parent.timestamp() + self.protocol_config.min_block_time.as_millis() as u64 {
+            return Err(BlockError::BlockTimeTooShort);
+        }
+
+        Ok(())
+    }
+
+    /// Verifies that a block produced after a view change carries the view change messages
+    /// of more than two thirds of the validators.
+    fn verify_view_change_proof(&self, block_number: u32, view_number: u16, proof: Option<&ViewChangeProof>) -> Result<(), BlockError> {
+        if view_number == 0 {
+            return Ok(());
         }
+
+        let proof = proof.ok_or(BlockError::MissingViewChangeMessages)?;
+        if !proof.has_distinct_signers(self.validators.len())
+            || proof.num_signers() <= usize::from(self.protocol_config.two_third_threshold()) {
+            return Err(BlockError::InvalidViewChangeMessages);
+        }
+
+        // Verify aggregate view change signatures.
+        let expected_message = ViewChangeInternals {
+            block_number,
+            new_view_number: view_number,
+        };
+        let aggregate_key = AggregatePublicKey::from(get_validators(&self.validators, &proof.public_key_bitmap));
+        if !proof.signatures.verify_single(&aggregate_key, &expected_message) {
+            return Err(BlockError::InvalidViewChangeMessages);
+        }
+
+        Ok(())
     }
 
     /// Verifies the validity of a micro block.
-    fn verify_micro_block(&self, block: &MicroBlock) -> Result<(), BlockError> {
+    fn verify_micro_block(&self, block: &MicroBlock, now: u64) -> Result<(), BlockError> {
         let block_number = block.header.digest.block_number;
+        // Check that we know the parent, which also ensures that it is not before the last macro block.
+        let parent = self.chain.get_by_hash(&block.header.parent_hash)
+            .ok_or(BlockError::UnknownParent)?;
+
         // Check valid block number.
-        if block_number > self.next_block_number()
-            || block_number <= self.last_macro_block() {
+        if block_number != parent.block_number() + 1 {
             return Err(BlockError::InvalidBlockNumber);
         }
 
@@ -358,21 +681,34 @@ impl HonestProtocol {
             return Err(BlockError::InvalidBlockType);
         }
 
+        // Check that the producer has been selected by the parent's seed.
+        let producer = self.get_producer_after(parent, block.header.digest.view_number);
+        if block.header.digest.validator != producer {
+            return Err(BlockError::InvalidBlockProducer);
+        }
+
         // Check Signature.
-        if !block.justification.verify(&block.header.digest.validator, &block.header) {
+        if !block.justification.verify(&producer, &block.header) {
             return Err(BlockError::InvalidSignature);
         }
 
-        // Get potentially conflicting block.
-        let other: Option<&Block> = self.chain.get(block_number as usize);
+        // Check that the seed is derived from the parent's seed.
+        if !block.extrinsics.seed.verify(&producer, &parent.seed().hash()) {
+            return Err(BlockError::InvalidSeed);
+        }
 
-        // Check whether we committed not to accept blocks from this view change number.
+        // Check whether we committed not to accept blocks from this view change number,
+        // regardless of whether the block builds on our head.
+        // Once others built on the block, the validators moved on without our view change.
+        let mut equivocation = None;
         if block_number == self.next_block_number() {
-            if block.header.digest.view_number < self.view_change_state.view_number {
+            if block.header.digest.view_number < self.view_change_state.min_view_number()
+                && !self.orphans.contains_key(&block.header.hash()) {
                 return Err(BlockError::OldViewChangeNumber);
             }
-        } else {
-            let other = other.unwrap();
+        } else if self.chain.is_on_main_chain(parent) {
+            // The block forks off our main chain, get the conflicting block.
+            let other = self.chain.get(block_number).unwrap();
             match block.header.digest.view_number.cmp(&other.view_number()) {
                 Ordering::Less => {
                     return Err(BlockError::OldViewChangeNumber);
@@ -383,7 +719,8 @@ impl HonestProtocol {
                         Block::Micro(other) => other,
                         _ => unreachable!(),
                     };
-                    return Err(BlockError::MicroBlockFork(SlashInherent {
+                    // The block is only reported after all other checks passed.
+                    equivocation = Some(Box::new(SlashInherent {
                         header1: block.header.clone(),
                         justification1: block.justification.clone(),
                         header2: other_micro.header.clone(),
@@ -393,39 +730,39 @@ impl HonestProtocol {
                 _ => {},
             }
         }
+        // Blocks extending other forks are resolved by the fork choice rule.
 
-        if block.header.digest.view_number > 0 {
-            // Verify aggregate view change signatures.
-            if let Some(ref view_change_proof) = block.extrinsics.view_change_messages {
-                let expected_message = ViewChangeInternals {
-                    block_number: block.header.digest.block_number,
-                    new_view_number: block.header.digest.view_number,
-                };
-                let keys = get_validators(&self.validators, &view_change_proof.public_key_bitmap);
-                let aggregate_key = AggregatePublicKey::from(keys);
-                if !view_change_proof.signatures.verify_single(&aggregate_key, &expected_message) {
-                    return Err(BlockError::InvalidViewChangeMessages);
-                }
-            } else {
-                return Err(BlockError::MissingViewChangeMessages);
-            }
+        self.verify_view_change_proof(block_number, block.header.digest.view_number, block.extrinsics.view_change_messages.as_ref())?;
+
+        // Check timestamp.
+        self.verify_timestamp(block.extrinsics.timestamp, parent, now)?;
+
+        // Check that the header commits to the extrinsics.
+        if block.header.extrinsics_root != block.extrinsics.hash() {
+            return Err(BlockError::InvalidExtrinsicsRoot);
         }
 
-        // TODO: Check timestamp.
         // TODO: Check transactions.
-        // TODO: Check slash inherents.
-        // TODO: Check Merkle hashes.
-        // TODO: Check for conflicting block.
-        // TODO: Check prev hash.
+
+        // Check that included slashes prove an equivocation.
+        if !block.extrinsics.slash_inherents.iter().all(SlashInherent::verify) {
+            return Err(BlockError::InvalidSlashInherent);
+        }
+
+        if let Some(slash) = equivocation {
+            return Err(BlockError::MicroBlockFork(slash));
+        }
 
         Ok(())
     }
 
     /// Verifies the validity of a micro block.
-    fn verify_macro_block(&self, block: &MacroBlock, proposal: bool) -> Result<(), BlockError> {
+    fn verify_macro_block(&self, block: &MacroBlock, proposal: bool, now: u64) -> Result<(), BlockError> {
         let block_number = block.header.digest.block_number;
         // Check valid block number.
-        if block_number != self.next_block_number() {
+        // Committed macro blocks are also accepted by nodes that are missing micro blocks of the epoch.
+        if (proposal && block_number != self.next_block_number())
+            || block_number != self.next_macro_block() {
             return Err(BlockError::InvalidBlockNumber);
         }
 
@@ -434,13 +771,41 @@ impl HonestProtocol {
             return Err(BlockError::InvalidBlockType);
         }
 
+        // Check that the block builds on the last macro block.
+        if block.header.digest.parent_macro_hash != self.last_macro_block_hash() {
+            return Err(BlockError::InvalidParentMacroHash);
+        }
+
+        // Proposals must build on our head.
+        // Committed macro blocks are final and thus we switch to their fork,
+        // or skip the micro blocks of the epoch if we do not know the parent.
+        let known_parent = self.chain.get_by_hash(&block.header.parent_hash);
+        let parent = match known_parent {
+            Some(parent) => parent,
+            None if !proposal => self.chain.get(self.last_macro_block()).unwrap(),
+            None => return Err(BlockError::UnknownParent),
+        };
+        if (parent.block_type() == BlockType::Micro && parent.block_number() + 1 != block_number)
+            || (proposal && *parent != *self.chain.head()) {
+            return Err(BlockError::InvalidParentHash);
+        }
+
+        // Check that the seed is derived from the parent's seed by the selected producer.
+        // Without the parent, we rely on the justification.
+        if let Some(parent) = known_parent {
+            let producer = self.get_producer_after(parent, block.header.digest.view_number);
+            if !block.extrinsics.seed.verify(&producer, &parent.seed().hash()) {
+                return Err(BlockError::InvalidSeed);
+            }
+        }
+
         let hash = block.header.hash();
 
         // Check Signature (if not a proposal).
         match (proposal, &block.justification) {
             (true, _) => {},
             (false, Some(justification)) =>  {
-                if !justification.verify(&self.validators, &hash) {
+                if !justification.verify(&self.validators, &hash, self.protocol_config.two_third_threshold()) {
                     return Err(BlockError::InvalidSignature);
                 }
             },
@@ -449,36 +814,37 @@ impl HonestProtocol {
             },
         }
 
-        // Check whether we committed not to accept blocks from this view change number.
-        if block.header.digest.view_number < self.view_change_state.view_number {
-            return Err(BlockError::OldViewChangeNumber);
-        }
+        self.verify_view_change_proof(block_number, block.header.digest.view_number, block.extrinsics.view_change_messages.as_ref())?;
 
-        if block.header.digest.view_number > 0 {
-            // Verify aggregate view change signatures.
-            if let Some(ref view_change_proof) = block.extrinsics.view_change_messages {
-                let expected_message = ViewChangeInternals {
-                    block_number: block.header.digest.block_number,
-                    new_view_number: block.header.digest.view_number,
-                };
-                let keys = get_validators(&self.validators, &view_change_proof.public_key_bitmap);
-                let aggregate_key = AggregatePublicKey::from(keys);
-                if !view_change_proof.signatures.verify_single(&aggregate_key, &expected_message) {
-                    return Err(BlockError::InvalidViewChangeMessages);
-                }
-            } else {
-                return Err(BlockError::MissingViewChangeMessages);
-            }
+        // Check timestamp.
+        self.verify_timestamp(block.extrinsics.timestamp, parent, now)?;
+
+        // Check that the header commits to the extrinsics.
+        if block.header.extrinsics_root != block.extrinsics.hash() {
+            return Err(BlockError::InvalidExtrinsicsRoot);
         }
 
-        // TODO: Check timestamp.
-        // TODO: Check Merkle hashes.
         // TODO: Check validator list.
-        // TODO: Check prev hash.
 
         Ok(())
     }
 
+    /// Returns the locked proposal with the latest prepare certificate
+    /// from our own state and the view change messages that led to the current view.
+    fn latest_locked_proposal(&self) -> Option<Box<LockedProposal>> {
+        let view_changes = self.view_change_state.view_change_messages
+            .get(&self.view_change_state.view_number)
+            .into_iter()
+            .flatten()
+            .filter_map(|view_change| view_change.locked.as_ref());
+
+        self.macro_block_state.locked.iter()
+            .chain(view_changes)
+            .filter(|locked| locked.verify(&self.validators, self.protocol_config.two_third_threshold()))
+            .max_by_key(|locked| locked.certificate.view_number)
+            .cloned()
+    }
+
     /// Calculates a new validator list.
     fn compute_validators(&self, _block_number: u32, _seed: &Signature<Seed>) -> Vec<PublicKey> {
         // TODO: Actually choose validators.
@@ -488,21 +854,36 @@ impl HonestProtocol {
     /// Called if we are the block producer and builds a block.
     fn produce_block(&mut self, env: &mut Environment<Event, MetricsEventType>) {
         let block_number = self.next_block_number();
+
+        // Re-propose the locked proposal from the latest view, if any.
+        if self.block_type_at(block_number) == BlockType::Macro {
+            if let Some(locked) = self.latest_locked_proposal() {
+                let LockedProposal { proposal, signature, certificate } = *locked;
+                let certificate = Some(Box::new(certificate));
+                self.multicast_to_validators(Event::BlockProposal(proposal.clone(), signature.clone(), certificate.clone()), env);
+                self.processed_proposal(proposal, signature, certificate, env);
+                return;
+            }
+        }
+
         let view_messages = self.view_change_state.view_change_messages
             .get(&self.view_change_state.view_number)
             .map(|set| AggregateProof::create_from_view_change(set, &self.validators));
 
-        let previous_block: &Block = self.chain.get(block_number as usize - 1).unwrap();
+        let previous_block: &Block = self.chain.get(block_number - 1).unwrap();
         let seed = self.key_pair.secret_key().sign(&previous_block.seed().hash());
+        // Never stamp a block earlier than the minimum block time after its predecessor, even if our clock is behind.
+        let timestamp = u64::max(self.local_timestamp(env),
+                                 previous_block.timestamp() + self.protocol_config.min_block_time.as_millis() as u64);
 
         // TODO Fill block.
         let block = match self.block_type_at(block_number) {
             BlockType::Micro => {
                 let extrinsics = MicroExtrinsics {
-                    timestamp: 0,
+                    timestamp,
                     seed,
                     view_change_messages: view_messages,
-                    slash_inherents: Vec::new(),
+                    slash_inherents: self.pending_slashes.clone(),
                     transactions: Vec::new(),
                 };
 
@@ -530,11 +911,11 @@ impl HonestProtocol {
                     validators: self.compute_validators(block_number, &seed),
                     block_number,
                     view_number: self.view_change_state.view_number,
-                    parent_macro_hash: self.chain.get(self.last_macro_block() as usize).map(|block| block.hash()).unwrap(),
+                    parent_macro_hash: self.last_macro_block_hash(),
                 };
 
                 let extrinsics = MacroExtrinsics {
-                    timestamp: 0,
+                    timestamp,
                     seed,
                     view_change_messages: view_messages,
                 };
@@ -558,56 +939,821 @@ impl HonestProtocol {
         env.schedule_self(Event::BlockProduced(block), processing_time);
     }
 
-    /// Called after successful block production.
-    pub fn produced_block(&mut self, block: Block, env: &mut Environment<Event, MetricsEventType>) {
-        match block {
-            block @ Block::Micro(_) => {
-                self.store_block(block.clone());
-                self.relay(Event::Block(block), env);
-                self.prepare_next_block(env);
-            },
-            Block::Macro(proposal) => {
-                let signature = self.key_pair.secret_key().sign(&proposal.header);
-                self.multicast_to_validators(Event::BlockProposal(proposal.clone(), signature.clone()), env);
-                self.processed_proposal(proposal, signature, env);
-            },
-        }
-    }
-
     /// Calculates the next block producer from the validator list.
     fn get_producer_at(&self, block_number: u32, view_number: u16) -> PublicKey {
         // The block must not be before the last macro block.
         // Last macro block is at block_number - (block_number % num_micro_blocks + 1)
         assert!(block_number > self.last_macro_block(), "Block {} is from before last macro block ({}), state: {:?}", block_number, self.last_macro_block(), self);
 
-        let previous_block: &Block = self.chain.get(block_number as usize - 1).unwrap();
+        let previous_block: &Block = self.chain.get(block_number - 1).unwrap();
+        self.get_producer_after(previous_block, view_number)
+    }
 
-        // H(S || i)
-        let r = Hasher::default()
-            .chain(&previous_block.seed().to_bytes())
-            .chain(&view_number.to_be_bytes())
-            .result();
-        let r: BigUint = BigUint::from_bytes_be(r.as_ref()) % self.validators.len();
-        let r = r.to_usize().unwrap();
-        self.validators[r].clone()
+    /// Calculates the producer of the block following `previous_block` from the seed of `previous_block`.
+    fn get_producer_after(&self, previous_block: &Block, view_number: u16) -> PublicKey {
+        self.validators[producer_index(previous_block, view_number, self.validators.len())].clone()
     }
 
+    /// Sends an event to all peers, except light peers.
     fn relay(&self, event: Event, env: &mut Environment<Event, MetricsEventType>) {
-        env.broadcast(event);
+        let peers: Vec<UniqueId> = env.peers().iter()
+            .filter(|peer| !self.light_peers.contains(peer))
+            .cloned()
+            .collect();
+        for peer in peers {
+            env.send_to(peer, event.clone());
+        }
     }
 
-    fn multicast_to_validators(&self, event: Event, env: &mut Environment<Event, MetricsEventType>) {
-        // TODO: Only send to validators.
-        env.broadcast(event);
+    /// Sends a PBFT message to all validators.
+    /// Depending on the configuration, this either floods the message to all peers,
+    /// or sends it directly to validators and falls back to gossip over the validator overlay
+    /// if not all validators are directly connected to us.
+    fn multicast_to_validators(&mut self, event: Event, env: &mut Environment<Event, MetricsEventType>) {
+        match self.protocol_config.dissemination {
+            Dissemination::Flood => self.relay(event, env),
+            Dissemination::Validators => {
+                let own_id = env.own_id();
+                let validator_peers = self.validator_peers(env);
+                let all_connected = self.validator_ids.iter()
+                    .all(|id| *id == own_id || validator_peers.contains(id));
+
+                let event = if all_connected {
+                    event
+                } else {
+                    // Do not relay our own message once it comes back to us.
+                    if let Some(id) = GossipId::from_event(&event) {
+                        self.known_gossip.insert(id);
+                    }
+                    Event::ValidatorGossip(Box::new(event))
+                };
+
+                for &peer in validator_peers.iter() {
+                    env.send_to(peer, event.clone());
+                }
+            },
+        }
     }
-}
+
+    /// Returns those of our peers that are validators.
+    fn validator_peers(&self, env: &Environment<Event, MetricsEventType>) -> Vec<UniqueId> {
+        env.peers().iter()
+            .filter(|peer| self.validator_ids.contains(peer))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Protocol for HonestProtocol {
+    fn init(&mut self, env: &mut Environment<Event, MetricsEventType>) {
+        self.prepare_next_block(env);
+    }
+
+    /// Returns the next block number.
+    fn current_block_number(&self) -> u32 {
+        self.chain.head().block_number()
+    }
+
+    /// A block has been received, simulate processing.
+    /// Blocks with an unknown parent are buffered and the missing blocks are requested from the sender.
+    fn received_block(&mut self, block: Block, from: UniqueId, env: &mut Environment<Event, MetricsEventType>) {
+        // Check whether we already received this block.
+        let hash = block.hash();
+        if self.known_blocks.contains(&hash) {
+            return;
+        }
+        self.known_blocks.insert(hash);
+
+        // Blocks from before the last macro block are not of interest anymore.
+        if block.block_number() <= self.last_macro_block() {
+            return;
+        }
+
+        // Committed macro blocks can be verified without the micro blocks of their epoch.
+        let macro_sync = block.block_type() == BlockType::Macro
+            && block.block_number() == self.next_macro_block();
+        if self.chain.get_by_hash(block.parent_hash()).is_none() && !macro_sync {
+            self.add_orphan(block, from, env);
+            return;
+        }
+
+        let processing_time = env.time() + self.timing.block_processing_time(&block);
+        self.schedule_processing(block, processing_time, env);
+    }
+
+    /// Answers a block request with the blocks we know.
+    /// Light nodes requesting macro headers are sent the headers of future macro blocks, too.
+    fn handle_block_request(&mut self, request: &BlockRequest, from: UniqueId, env: &mut Environment<Event, MetricsEventType>) {
+        if let BlockRequest::MacroHeaders(block_number) = request {
+            self.light_peers.insert(from);
+            env.send_to(from, Event::MacroHeaders(self.chain.macro_headers_since(*block_number)));
+            return;
+        }
+
+        let blocks = match request {
+            BlockRequest::Hash(hash) => self.chain.get_by_hash(hash).cloned().into_iter().collect(),
+            BlockRequest::Range(start, end) => self.chain.range(*start, *end),
+            BlockRequest::MacroBlocks(block_number) => self.chain.macro_blocks_since(*block_number),
+            BlockRequest::MacroHeaders(_) => unreachable!(),
+        };
+        env.send_to(from, Event::BlockResponse(request.clone(), blocks));
+    }
+
+    /// Processes requested blocks one after the other.
+    /// This includes blocks we already know, but could not connect to our chain yet.
+    /// If the response to the pending request contains nothing new while we still miss blocks, another peer is asked.
+    fn handle_block_response(&mut self, request: &BlockRequest, blocks: Vec<Block>, env: &mut Environment<Event, MetricsEventType>) {
+        // Late responses to earlier requests do not settle the pending request.
+        let pending = match self.request_pending {
+            Some(ref pending) if pending.request == *request => self.request_pending.take(),
+            _ => None,
+        };
+
+        let mut processing_time = env.time();
+        let mut num_new_blocks = 0;
+        for block in blocks {
+            let hash = block.hash();
+            if self.chain.contains(&block) || block.block_number() <= self.last_macro_block() {
+                continue;
+            }
+            self.known_blocks.insert(hash);
+            num_new_blocks += 1;
+
+            processing_time += self.timing.block_processing_time(&block);
+            self.schedule_processing(block, processing_time, env);
+        }
+
+        if pending.is_some() && num_new_blocks == 0 && !self.orphans.is_empty() {
+            self.request_pending = pending;
+            self.retry_request(env);
+        }
+    }
+
+    /// Called when a view change message has been received.
+    /// First check validity, then add message.
+    /// If we received enough view change messages for this view change number,
+    /// stop accepting blocks for this number and move on.
+    /// In this case, also check for next block producer or start timeout.
+    fn handle_view_change(&mut self, view_change: ViewChange, env: &mut Environment<Event, MetricsEventType>) {
+        if !self.is_validator() {
+            return;
+        }
+
+        if view_change.internals.block_number <= self.current_block_number() {
+            self.handle_past_view_change(view_change, env);
+            return;
+        }
+
+        // Validate view change message:
+        // Should be for current block and have a valid signature.
+        if view_change.internals.block_number != self.next_block_number()
+            || !view_change.verify() {
+            return;
+        }
+
+        // A locked proposal must be for the current block and carry a valid prepare certificate.
+        if let Some(ref locked) = view_change.locked {
+            if locked.proposal.header.digest.block_number != self.next_block_number()
+                || !locked.verify(&self.validators, self.protocol_config.two_third_threshold()) {
+                return;
+            }
+        }
+
+        let new_view_number = view_change.internals.new_view_number;
+        self.view_change_state.add_message(view_change);
+
+        // More than f validators gave up on the earlier views, so at least one honest validator did.
+        if new_view_number > self.view_change_state.min_view_number()
+            && self.view_change_state.num_messages(new_view_number) > self.protocol_config.max_malicious() {
+            self.send_view_change(new_view_number, env);
+        }
+
+        // When 2f + 1 view change messages have been received,
+        // commit to not accepting a block from the previous owner anymore.
+        // Views without a quorum are skipped.
+        if new_view_number > self.view_change_state.view_number
+            && self.view_change_state.num_messages(new_view_number) > self.protocol_config.two_third_threshold() {
+            self.view_change_state.view_number = new_view_number;
+
+            // Drop the current proposal, but keep the proposal we are locked on.
+            self.macro_block_state.next_view();
+
+            self.prepare_next_block(env);
+        }
+    }
+
+    /// Handles a macro block proposal.
+    fn handle_macro_block_proposal(&mut self, proposal: MacroBlock, signature: Signature<MacroHeader>, certificate: Option<Box<PrepareCertificate>>, env: &mut Environment<Event, MetricsEventType>) {
+        // Only validators take part in PBFT, full nodes wait for the committed macro block.
+        if !self.is_validator() {
+            return;
+        }
+
+        // Check whether we already received this proposal.
+        let id = (proposal.hash(), certificate.as_ref().map(|certificate| certificate.view_number));
+        if self.known_proposals.contains(&id) {
+            return;
+        }
+        self.known_proposals.insert(id);
+
+        let processing_time = env.time() + self.timing.proposal_processing_time(&proposal, &signature, certificate.as_deref());
+        env.schedule_self(Event::ProposalProcessed(proposal, signature, certificate), processing_time);
+    }
+
+    /// Handles an incoming prepare message.
+    /// Votes are collected per proposal, even before we know the proposal.
+    fn handle_prepare(&mut self, prepare: PbftProof, env: &mut Environment<Event, MetricsEventType>) {
+        if !self.is_validator() || !prepare.verify_prepare() || self.macro_block_state.has_prepare(&prepare) {
+            return;
+        }
+
+        self.macro_block_state.add_prepare(prepare);
+        self.check_prepared(env);
+    }
+
+    /// Handles an incoming commit message.
+    /// Votes are collected per proposal, even before we know the proposal.
+    fn handle_commit(&mut self, commit: PbftProof, env: &mut Environment<Event, MetricsEventType>) {
+        if !self.is_validator() || !commit.verify_commit() || self.macro_block_state.has_commit(&commit) {
+            return;
+        }
+
+        self.macro_block_state.add_commit(commit);
+        self.check_committed(env);
+    }
+
+    /// Handles an aggregate received over Handel, simulate verification.
+    fn handle_contribution(&mut self, contribution: HandelContribution, env: &mut Environment<Event, MetricsEventType>) {
+        match self.handel {
+            Some(ref handel) if handel.is_valid_level(&contribution) => {},
+            _ => return,
+        }
+
+        let processing_time = env.time() + contribution.verification_time(&self.timing);
+        env.schedule_self(Event::HandelContributionProcessed(contribution), processing_time);
+    }
+
+    /// Called when a PBFT message has been gossiped to us over the validator overlay.
+    /// Relays new messages to all validators we are connected to (except the sender)
+    /// and returns whether the message should be processed.
+    fn relay_validator_gossip(&mut self, event: &Event, from: UniqueId, env: &mut Environment<Event, MetricsEventType>) -> bool {
+        let id = match GossipId::from_event(event) {
+            Some(id) => id,
+            None => return false,
+        };
+        if !self.known_gossip.insert(id) {
+            return false;
+        }
+
+        for peer in self.validator_peers(env) {
+            if peer != from {
+                env.send_to(peer, Event::ValidatorGossip(Box::new(event.clone())));
+            }
+        }
+        true
+    }
+
+    /// A block has been processed, ensure its validity.
+    /// If it is invalid, ignore it.
+    /// If it is valid, store block and reset state.
+    fn processed_block(&mut self, block: Block, env: &mut Environment<Event, MetricsEventType>) {
+        self.processing_blocks.remove(&block.hash());
+
+        // Requested blocks might have been stored while they were processed.
+        if self.chain.contains(&block) {
+            return;
+        }
+
+        // We verify the block.
+        let result = self.verify_block(&block, self.local_timestamp(env));
+
+        // TODO: Detect equivocations off the main chain (we currently do not store the headers of known blocks).
+
+        match result {
+            Ok(()) => {},
+            Err(BlockError::MicroBlockFork(slash)) => {
+                // Remember the proof of equivocation to include it in our next block.
+                // Otherwise, the block is valid and left to the fork choice rule.
+                debug!("Got conflicting block #{} from {:?}", slash.header1.digest.block_number, slash.header1.digest.validator);
+                if !self.pending_slashes.iter().any(|pending| pending.header1.digest == slash.header1.digest) {
+                    self.pending_slashes.push(*slash);
+                }
+            },
+            Err(e) => {
+                // Ignore block.
+                warn!("Got invalid block, reason {:?}", e);
+                return;
+            },
+        }
+
+        // Blocks on inferior forks are stored, but do not change our state.
+        match self.store_block(block.clone(), env) {
+            Ok(PushResult::Forked) => {},
+            Ok(_) => {
+                // Relay block.
+                self.relay(Event::Block(block), env);
+
+                self.prepare_next_block(env);
+            },
+            Err(e) => warn!("Could not store block, reason {:?}", e),
+        }
+    }
+
+    /// Called after successful block production.
+    fn produced_block(&mut self, block: Block, env: &mut Environment<Event, MetricsEventType>) {
+        match block {
+            block @ Block::Micro(_) => {
+                // Blocks received while producing, e.g. a macro block pruning our parent, may have made it stale.
+                let result = self.verify_block(&block, self.local_timestamp(env))
+                    .and_then(|_| self.store_block(block.clone(), env));
+                if let Err(e) = result {
+                    debug!("Dropping produced block {}, reason {:?}", block, e);
+                    return;
+                }
+                self.relay(Event::Block(block), env);
+                self.prepare_next_block(env);
+            },
+            Block::Macro(proposal) => {
+                let signature = self.key_pair.secret_key().sign(&proposal.header);
+                self.multicast_to_validators(Event::BlockProposal(proposal.clone(), signature.clone(), None), env);
+                self.processed_proposal(proposal, signature, None, env);
+            },
+        }
+    }
+
+    /// A macro block proposal has been processed.
+    fn processed_proposal(&mut self, proposal: MacroBlock, signature: Signature<MacroHeader>, certificate: Option<Box<PrepareCertificate>>, env: &mut Environment<Event, MetricsEventType>) {
+        // No duplicate proposal processing.
+        if self.macro_block_state.proposal.is_some() {
+            return;
+        }
+
+        // Proposals relayed after we accepted their block are not of interest anymore.
+        if proposal.header.digest.block_number < self.next_block_number() {
+            return;
+        }
+
+        // We verify the proposal first.
+        let mut result = self.verify_macro_block(&proposal, true, self.local_timestamp(env));
+
+        // Check block producer (only possible if the block number is valid).
+        if result.is_ok() {
+            let public_key = self.get_producer_at(proposal.header.digest.block_number, proposal.header.digest.view_number);
+            if !signature.verify(&public_key,
+                                 &proposal.header) {
+                result = Err(BlockError::InvalidBlockProducer);
+            }
+        }
+
+        if result.is_ok() {
+            result = self.verify_lock(&proposal, certificate.as_deref());
+        }
+
+        if let Err(ref e) = result {
+            warn!("Got invalid block proposal, reason {:?}", e);
+        }
+
+        if result.is_ok() {
+            // Update state.
+            self.macro_block_state.proposal = Some((proposal.clone(), signature.clone()));
+            self.macro_block_state.phase = MacroBlockPhase::PROPOSED;
+
+            let hash = proposal.header.hash();
+            // Relay block.
+            // When disseminating to validators only, the proposer already reaches all of them.
+            if self.protocol_config.dissemination == Dissemination::Flood {
+                self.relay(Event::BlockProposal(proposal, signature, certificate), env);
+            }
+
+            // Send and process prepare message.
+            let prepare = PbftProof::prepare(&hash, self.view_change_state.view_number, &self.key_pair.secret_key());
+            self.send_vote(prepare.clone(), Event::BlockPrepare(prepare.clone()), env);
+
+            self.handle_prepare(prepare, env);
+            // Votes of this view might have arrived before the proposal.
+            self.check_prepared(env);
+        } else {
+            // Ignore block.
+        }
+    }
+
+    /// An aggregate received over Handel has been verified.
+    /// If it improves on the best aggregate of its level, process the votes it contains.
+    fn processed_contribution(&mut self, contribution: HandelContribution, env: &mut Environment<Event, MetricsEventType>) {
+        let aggregate_key = AggregatePublicKey::from(get_validators(&self.validators, &contribution.proof.public_key_bitmap));
+        if !contribution.proof.signatures.verify_single(&aggregate_key, &contribution.message) {
+            warn!("Got invalid aggregate {}", contribution);
+            return;
+        }
+
+        let handel = match self.handel {
+            Some(ref mut handel) => handel,
+            None => return,
+        };
+
+        let message = contribution.message.clone();
+        let level = contribution.level;
+        let votes: Vec<PbftProof> = contribution.proof.signatures.signatures()
+            .cloned()
+            .map(PbftProof::from_signature)
+            .collect();
+        if !handel.add_contribution(contribution) {
+            return;
+        }
+        let level_complete = handel.is_level_complete(&message, level);
+
+        for vote in votes {
+            match message {
+                PbftMessage::Prepare(..) => self.handle_prepare(vote, env),
+                PbftMessage::Commit(_) => self.handle_commit(vote, env),
+            }
+        }
+
+        // Pass on the aggregate of a complete level right away.
+        if level_complete {
+            self.send_contributions(&message, env);
+        }
+    }
+
+    /// The minimum block time has passed, produce the block if we are still expected to.
+    /// Our head might have changed to a block of the same number in the meantime.
+    fn handle_production_due(&mut self, block_number: u32, view_number: u16, env: &mut Environment<Event, MetricsEventType>) {
+        if self.next_block_number() == block_number && self.view_change_state.view_number == view_number
+            && self.get_producer_at(block_number, view_number) == self.key_pair.public_key() {
+            self.produce_block(env);
+        }
+    }
+
+    /// Called when a timeout has been triggered.
+    /// Check whether a corresponding (valid) block has been received in the meantime.
+    /// If not, prepare and send out view change message.
+    fn handle_timeout(&mut self, block_number: u32, view_number: u16, env: &mut Environment<Event, MetricsEventType>) {
+        // Check whether timeout was triggered and no new block has been accepted in the meanwhile.
+        // If we joined a view change meanwhile, its own timeout applies.
+        if self.next_block_number() == block_number && self.view_change_state.view_number == view_number
+            && self.view_change_state.sent_view_number <= view_number {
+            self.start_view_change(env);
+        }
+    }
+
+    /// Called when a view change has not succeeded in time.
+    /// Unless we moved on meanwhile, we send a view change message for the view after it.
+    fn handle_view_change_timeout(&mut self, block_number: u32, view_number: u16, env: &mut Environment<Event, MetricsEventType>) {
+        if self.next_block_number() == block_number && self.view_change_state.view_number < view_number
+            && self.view_change_state.sent_view_number == view_number {
+            self.start_view_change(env);
+        }
+    }
+
+    /// Called when a block request has not been answered in time.
+    /// Unless a later attempt is pending, ask another peer.
+    fn handle_request_timeout(&mut self, id: u32, env: &mut Environment<Event, MetricsEventType>) {
+        if self.request_pending.as_ref().is_some_and(|pending| pending.id == id) {
+            self.retry_request(env);
+        }
+    }
+
+    /// Called when a macro block timeout has been triggered.
+    /// If PBFT made progress since the timeout has been set, the timeout is extended.
+    /// Otherwise, send out a view change message that carries the proposal we are locked on.
+    fn handle_macro_block_timeout(&mut self, block_number: u32, view_number: u16, phase: MacroBlockPhase, env: &mut Environment<Event, MetricsEventType>) {
+        if self.next_block_number() != block_number || self.view_change_state.view_number != view_number
+            || self.view_change_state.sent_view_number > view_number {
+            return;
+        }
+
+        if self.macro_block_state.phase > phase {
+            self.schedule_timeout(env);
+        } else {
+            self.start_view_change(env);
+        }
+    }
+
+    /// Periodically sends aggregates until we have aggregated more than two thirds of the signatures.
+    /// Validators that fall behind receive the macro block once it has been accepted.
+    fn handle_handel_timeout(&mut self, message: PbftMessage, env: &mut Environment<Event, MetricsEventType>) {
+        match self.handel {
+            Some(ref handel) if handel.is_active(&message)
+                && handel.num_signers(&message) <= usize::from(self.protocol_config.two_third_threshold()) => {},
+            _ => return,
+        }
+
+        self.send_contributions(&message, env);
+        env.schedule_self(Event::HandelTimeout(message), env.time() + self.protocol_config.handel_period);
+    }
+}
 
 impl Debug for HonestProtocol {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         writeln!(f, "HonestProtocol {{")?;
         writeln!(f, "  key_pair: {:?},", self.key_pair)?;
         writeln!(f, "  head_number: {},", self.current_block_number())?;
-        writeln!(f, "  head: {:?},", self.chain.last().unwrap())?;
+        writeln!(f, "  head: {:?},", self.chain.head())?;
         writeln!(f, "}}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::BinaryHeap;
+
+    use simulator::Event as SimulatorEvent;
+    use simulator::NetworkConfig;
+    use simulator::Node;
+    use simulator::Simulator;
+
+    use crate::simulation::metrics::DefaultMetrics;
+
+    use super::*;
+
+    const NUM_VALIDATORS: u64 = 4;
+
+    fn protocol() -> HonestProtocol {
+        validator(genesis_block(), 0)
+    }
+
+    /// Every macro block directly follows the last one, such that the genesis block is followed by a proposal.
+    fn validator(genesis_block: MacroBlock, id: u64) -> HonestProtocol {
+        let zero = Duration::default();
+        let protocol_config = ProtocolConfig {
+            variant: Default::default(),
+            micro_block_timeout: Duration::from_secs(1),
+            macro_block_timeout: Duration::from_secs(1),
+            num_micro_blocks: 0,
+            num_validators: NUM_VALIDATORS as u16,
+            dissemination: Default::default(),
+            aggregation: Default::default(),
+            handel_period: zero,
+            min_block_time: zero,
+            max_timestamp_drift: zero,
+            backoff: Default::default(),
+            max_timeout: None,
+        };
+        let timing = Timing {
+            signing: zero,
+            verification: zero,
+            batch_verification: zero,
+            generate_aggregate_signature_same_message: zero,
+            generate_aggregate_public_key: zero,
+            verify_aggregate_signature_same_message: zero,
+            generate_aggregate_signature_distinct_message: zero,
+            verify_aggregate_signature_distinct_message: zero,
+        };
+        HonestProtocol::new(protocol_config, timing, genesis_block, KeyPair::from_id(id), 0)
+    }
+
+    fn genesis_block() -> MacroBlock {
+        MacroBlock::create_genesis_block(&(0..NUM_VALIDATORS as usize).collect())
+    }
+
+    /// A proposal for the first macro block, first proposed in the given view.
+    fn proposal(protocol: &HonestProtocol, view_number: u16) -> MacroBlock {
+        let mut proposal = genesis_block();
+        proposal.header.digest.block_number = protocol.next_macro_block();
+        proposal.header.digest.view_number = view_number;
+        proposal
+    }
+
+    /// A certificate with the prepares of the given validators in the given view.
+    fn prepare_certificate(protocol: &HonestProtocol, proposal: &MacroBlock, view_number: u16, signers: &[u64]) -> PrepareCertificate {
+        let hash = proposal.header.hash();
+        let prepares = signers.iter()
+            .map(|&id| PbftProof::prepare(&hash, view_number, &KeyPair::from_id(id).secret_key()))
+            .collect();
+        PrepareCertificate {
+            view_number,
+            prepare: AggregateProof::create(&prepares, &protocol.validators),
+        }
+    }
+
+    /// A valid proposal for the next block by the producer of the given view.
+    fn signed_proposal(protocol: &HonestProtocol, view_number: u16) -> (MacroBlock, Signature<MacroHeader>) {
+        let block_number = protocol.next_block_number();
+        let producer = KeyPair::from_id(protocol.get_producer_at(block_number, view_number).id());
+        let parent = protocol.chain.head();
+        let extrinsics = MacroExtrinsics {
+            timestamp: 0,
+            seed: producer.secret_key().sign(&parent.seed().hash()),
+            view_change_messages: protocol.view_change_state.view_change_messages.get(&view_number)
+                .map(|view_changes| AggregateProof::create_from_view_change(view_changes, &protocol.validators)),
+        };
+        let header = MacroHeader {
+            parent_hash: parent.hash(),
+            digest: MacroDigest {
+                validators: protocol.validators.clone(),
+                block_number,
+                view_number,
+                parent_macro_hash: protocol.last_macro_block_hash(),
+            },
+            extrinsics_root: extrinsics.hash(),
+            state_root: Hash::default(),
+        };
+        let signature = producer.secret_key().sign(&header);
+        (MacroBlock { header, extrinsics, justification: None }, signature)
+    }
+
+    /// Connects all validators, messages arrive instantly.
+    struct TestNetwork;
+
+    impl NetworkConfig for TestNetwork {
+        type EventType = Event;
+        type MetricsEventType = MetricsEventType;
+
+        fn num_nodes(&self) -> usize {
+            NUM_VALIDATORS as usize
+        }
+
+        fn adjacent(&self, from: usize) -> Cow<'_, Vec<usize>> {
+            Cow::Owned((0..self.num_nodes()).filter(|&id| id != from).collect())
+        }
+
+        fn full_transmission_time(&self, _from: usize, _to: usize, _event: &Event) -> Option<Duration> {
+            Some(Duration::default())
+        }
+
+        fn node(&self, _id: usize) -> Box<dyn Node<EventType=Event, MetricsEventType=MetricsEventType>> {
+            unreachable!()
+        }
+    }
+
+    /// Collects the events a single validator sends or schedules, without delivering them.
+    struct TestEnvironment {
+        network: TestNetwork,
+        metrics: DefaultMetrics,
+        queue: BinaryHeap<SimulatorEvent<Event>>,
+        time: Time,
+        own_id: UniqueId,
+    }
+
+    impl TestEnvironment {
+        fn new(own_id: UniqueId) -> Self {
+            TestEnvironment {
+                // Only the simulator starts the clock.
+                time: Simulator::new(TestNetwork, DefaultMetrics::default()).initial_time(),
+                network: TestNetwork,
+                metrics: DefaultMetrics::default(),
+                queue: BinaryHeap::new(),
+                own_id,
+            }
+        }
+
+        fn env(&mut self) -> Environment<'_, Event, MetricsEventType> {
+            Environment::new(self.own_id, &self.network, self.time, self.time, &mut self.queue, &mut self.metrics)
+        }
+
+        /// Returns the events since the last call.
+        fn events(&mut self) -> Vec<Event> {
+            self.queue.drain().map(|event| event.inner().clone()).collect()
+        }
+    }
+
+    /// Moves all validators on to the given view, our view change carries our lock.
+    fn change_view(protocol: &mut HonestProtocol, test: &mut TestEnvironment, view_number: u16) {
+        protocol.start_view_change(&mut test.env());
+        let own_id = test.own_id;
+        for id in (0..NUM_VALIDATORS).filter(|&id| id as UniqueId != own_id) {
+            let view_change = ViewChange::new(protocol.next_block_number(), view_number, None, &KeyPair::from_id(id).secret_key());
+            protocol.handle_view_change(view_change, &mut test.env());
+        }
+        assert_eq!(protocol.view_change_state.view_number, view_number);
+    }
+
+    fn lock(protocol: &mut HonestProtocol, proposal: &MacroBlock, certificate: PrepareCertificate) {
+        let signature = KeyPair::from_id(1).secret_key().sign(&proposal.header);
+        protocol.macro_block_state.locked = Some(Box::new(LockedProposal {
+            proposal: proposal.clone(),
+            signature,
+            certificate,
+        }));
+    }
+
+    #[test]
+    fn re_propose_locked_proposal_in_later_view() {
+        let mut protocol = protocol();
+        let locked = proposal(&protocol, 0);
+        let certificate = prepare_certificate(&protocol, &locked, 0, &[0, 1, 2, 3]);
+        lock(&mut protocol, &locked, certificate.clone());
+        protocol.view_change_state.view_number = 1;
+
+        assert_eq!(protocol.latest_locked_proposal().unwrap().proposal.header.hash(), locked.header.hash());
+        assert!(protocol.verify_lock(&locked, Some(&certificate)).is_ok());
+
+        // Other proposals need a certificate of a later view.
+        let other = proposal(&protocol, 1);
+        assert!(matches!(protocol.verify_lock(&other, None), Err(BlockError::LockedOnOtherProposal)));
+        let later = prepare_certificate(&protocol, &other, 1, &[0, 1, 2, 3]);
+        assert!(protocol.verify_lock(&other, Some(&later)).is_ok());
+    }
+
+    #[test]
+    fn reject_forged_and_stale_certificates() {
+        let mut protocol = protocol();
+        let locked = proposal(&protocol, 0);
+        let certificate = prepare_certificate(&protocol, &locked, 1, &[0, 1, 2, 3]);
+        lock(&mut protocol, &locked, certificate);
+        protocol.view_change_state.view_number = 2;
+
+        let other = proposal(&protocol, 1);
+
+        // A valid certificate from before our lock does not unlock us.
+        let stale = prepare_certificate(&protocol, &other, 0, &[0, 1, 2, 3]);
+        assert!(matches!(protocol.verify_lock(&other, Some(&stale)), Err(BlockError::LockedOnOtherProposal)));
+
+        // Nor does the same certificate claiming a later view.
+        let mut forged = stale.clone();
+        forged.view_number = 2;
+        assert!(matches!(protocol.verify_lock(&other, Some(&forged)), Err(BlockError::InvalidPrepareCertificate)));
+
+        // Nor a certificate counting signers twice.
+        let mut duplicates = prepare_certificate(&protocol, &other, 2, &[0, 1]);
+        duplicates.prepare.public_key_bitmap.extend_from_slice(&[0, 1]);
+        assert!(matches!(protocol.verify_lock(&other, Some(&duplicates)), Err(BlockError::InvalidPrepareCertificate)));
+
+        // Nor a certificate from a future view.
+        let future = prepare_certificate(&protocol, &other, 3, &[0, 1, 2, 3]);
+        assert!(matches!(protocol.verify_lock(&other, Some(&future)), Err(BlockError::InvalidPrepareCertificate)));
+
+        // A forged lock in a view change message is not re-proposed.
+        let signature = KeyPair::from_id(1).secret_key().sign(&other.header);
+        let forged_lock = LockedProposal { proposal: other, signature, certificate: forged };
+        protocol.view_change_state.add_message(ViewChange::new(protocol.next_block_number(), 2, Some(Box::new(forged_lock)), &KeyPair::from_id(1).secret_key()));
+        assert_eq!(protocol.latest_locked_proposal().unwrap().proposal.header.hash(), locked.header.hash());
+    }
+
+    #[test]
+    fn no_conflicting_commit_across_view_change() {
+        let genesis_block = genesis_block();
+        let producer = {
+            let protocol = validator(genesis_block.clone(), 0);
+            move |view_number| protocol.get_producer_at(1, view_number).id()
+        };
+
+        // Another validator leads view 1, we lead the next view with a different leader.
+        let leader_view = (2..).find(|&view_number| producer(view_number) != producer(1)).unwrap();
+        let own_id = producer(leader_view);
+        let others: Vec<u64> = (0..NUM_VALIDATORS).filter(|&id| id != own_id).collect();
+        let key = |id| KeyPair::from_id(id).secret_key();
+
+        let mut protocol = validator(genesis_block, own_id);
+        let mut test = TestEnvironment::new(own_id as UniqueId);
+
+        // In view 0, all validators prepare the proposal, but only one other commit reaches us.
+        let (locked, signature) = signed_proposal(&protocol, 0);
+        let locked_hash = locked.header.hash();
+        protocol.processed_proposal(locked.clone(), signature, None, &mut test.env());
+        for &id in others.iter() {
+            protocol.handle_prepare(PbftProof::prepare(&locked_hash, 0, &key(id)), &mut test.env());
+        }
+        protocol.handle_commit(PbftProof::commit(&locked_hash, &key(others[0])), &mut test.env());
+        assert_eq!(protocol.macro_block_state.phase, MacroBlockPhase::PREPARED);
+        assert_eq!(protocol.macro_block_state.locked.as_ref().unwrap().proposal.header.hash(), locked_hash);
+
+        // The leader of view 1 proposes a different block, which all other validators prepare and commit.
+        change_view(&mut protocol, &mut test, 1);
+        let (conflicting, signature) = signed_proposal(&protocol, 1);
+        let conflicting_hash = conflicting.header.hash();
+        test.events();
+        protocol.processed_proposal(conflicting, signature, None, &mut test.env());
+        for &id in others.iter() {
+            protocol.handle_prepare(PbftProof::prepare(&conflicting_hash, 1, &key(id)), &mut test.env());
+            protocol.handle_commit(PbftProof::commit(&conflicting_hash, &key(id)), &mut test.env());
+        }
+        protocol.check_committed(&mut test.env());
+        assert!(protocol.macro_block_state.proposal.is_none());
+        assert!(test.events().iter().all(|event| match event {
+            Event::BlockPrepare(vote) | Event::BlockCommit(vote) => *vote.hash() != conflicting_hash,
+            Event::BlockProcessed(_) => false,
+            _ => true,
+        }));
+
+        // Once we lead, we re-propose the locked proposal with its certificate.
+        for view_number in 2..=leader_view {
+            change_view(&mut protocol, &mut test, view_number);
+        }
+        let events = test.events();
+        let reproposal = events.iter().find_map(|event| match event {
+            Event::BlockProposal(proposal, _, Some(certificate)) => Some((proposal.header.hash(), certificate.view_number)),
+            _ => None,
+        });
+        assert_eq!(reproposal, Some((locked_hash.clone(), 0)));
+
+        // It is prepared again and committed, the conflicting proposal never is.
+        for &id in others.iter() {
+            protocol.handle_prepare(PbftProof::prepare(&locked_hash, leader_view, &key(id)), &mut test.env());
+        }
+        for &id in others[1..].iter() {
+            protocol.handle_commit(PbftProof::commit(&locked_hash, &key(id)), &mut test.env());
+        }
+        let committed: Vec<Block> = test.events().into_iter().filter_map(|event| match event {
+            Event::BlockProcessed(block) => Some(block),
+            _ => None,
+        }).collect();
+        assert_eq!(committed.len(), 1);
+        assert_eq!(committed[0].hash(), locked_hash);
+        match committed[0] {
+            Block::Macro(ref block) => assert!(block.justification.as_ref().unwrap().verify(&protocol.validators, &locked_hash, protocol.protocol_config.two_third_threshold())),
+            Block::Micro(_) => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use simulator::Environment;
+use simulator::metrics::Metrics;
+
+use crate::actors::Timing;
+use crate::datastructures::block::MacroBlock;
+use crate::datastructures::block::MacroHeader;
+use crate::datastructures::hash::Hash;
+use crate::datastructures::signature::PublicKey;
+use crate::datastructures::sync::BlockRequest;
+use crate::datastructures::sync::FinalizedHeader;
+use crate::protocol::BlockError;
+use crate::protocol::ProtocolConfig;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+
+/// A light node only follows the headers of finalised macro blocks
+/// and tracks the validator sets announced in them.
+pub struct LightProtocol {
+    protocol_config: ProtocolConfig,
+    timing: Timing,
+    head: MacroHeader,
+    // The validators that finalise the next macro block.
+    validators: Vec<PublicKey>,
+
+    // Do not process known headers.
+    known_headers: HashSet<Hash>,
+}
+
+impl LightProtocol {
+    /// Create a protocol instance.
+    pub fn new(protocol_config: ProtocolConfig, timing: Timing, genesis_block: MacroBlock) -> Self {
+        LightProtocol {
+            protocol_config,
+            timing,
+            validators: genesis_block.header.digest.validators.clone(),
+            head: genesis_block.header,
+
+            known_headers: HashSet::new(),
+        }
+    }
+
+    /// Number of the last macro block we know.
+    pub fn current_block_number(&self) -> u32 {
+        self.head.digest.block_number
+    }
+
+    /// Number of the next macro block.
+    fn next_macro_block(&self) -> u32 {
+        self.current_block_number() + self.protocol_config.num_micro_blocks + 1 /*macro block*/
+    }
+
+    /// Requests the headers we are missing from all peers,
+    /// which also subscribes us to the headers of future macro blocks.
+    pub fn request_headers(&self, env: &mut Environment<Event, MetricsEventType>) {
+        env.broadcast(Event::BlockRequest(BlockRequest::MacroHeaders(self.current_block_number())));
+    }
+
+    /// Headers have been received, simulate processing them one after the other.
+    pub fn received_headers(&mut self, headers: Vec<FinalizedHeader>, env: &mut Environment<Event, MetricsEventType>) {
+        let mut processing_time = env.time();
+        for header in headers {
+            if header.header.digest.block_number <= self.current_block_number()
+                || !self.known_headers.insert(header.hash()) {
+                continue;
+            }
+
+            processing_time += self.timing.header_processing_time(&header);
+            env.schedule_self(Event::MacroHeaderProcessed(header), processing_time);
+        }
+    }
+
+    /// A header has been processed, ensure its validity.
+    /// If it is valid, it becomes our new head and its validators finalise the next macro block.
+    /// If we missed a header in between, request the missing headers again.
+    pub fn processed_header(&mut self, header: FinalizedHeader, env: &mut Environment<Event, MetricsEventType>) {
+        let hash = header.hash();
+        match self.verify_header(&header) {
+            Ok(()) => {
+                self.validators = header.header.digest.validators.clone();
+                self.head = header.header;
+
+                env.note_event(&MetricsEventType::MacroHeaderAccepted {
+                    own: env.own_id(),
+                    hash,
+                }, env.time());
+            },
+            Err(BlockError::InvalidBlockNumber) if header.header.digest.block_number > self.next_macro_block() => {
+                // Process the header again once it is resent.
+                self.known_headers.remove(&hash);
+                self.request_headers(env);
+            },
+            Err(e) => {
+                warn!("Got invalid macro header, reason {:?}", e);
+            },
+        }
+    }
+
+    /// Verifies that a header directly follows our head and is finalised by the current validators.
+    fn verify_header(&self, header: &FinalizedHeader) -> Result<(), BlockError> {
+        // Check valid block number.
+        if header.header.digest.block_number != self.next_macro_block() {
+            return Err(BlockError::InvalidBlockNumber);
+        }
+
+        // Check that the header builds on the last macro block.
+        if header.header.digest.parent_macro_hash != self.head.hash() {
+            return Err(BlockError::InvalidParentMacroHash);
+        }
+
+        // Check that enough distinct validators prepared and committed the block.
+        if !header.justification.verify(&self.validators, &header.hash(), self.protocol_config.two_third_threshold()) {
+            return Err(BlockError::InvalidSignature);
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,13 @@
+use std::collections::HashMap;
 use std::collections::HashSet;
 
 use crate::datastructures::block::MacroBlock;
+use crate::datastructures::block::MacroHeader;
+use crate::datastructures::hash::Hash;
+use crate::datastructures::pbft::LockedProposal;
+use crate::datastructures::pbft::PbftMessage;
 use crate::datastructures::pbft::PbftProof;
+use crate::datastructures::signature::Signature;
 
 #[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
 pub enum MacroBlockPhase {
@@ -13,36 +19,56 @@ pub enum MacroBlockPhase {
 
 pub struct MacroBlockState {
     pub view_number: u16,
-    pub proposal: Option<MacroBlock>,
-    pub prepares: HashSet<PbftProof>,
-    pub commits: HashSet<PbftProof>,
+    pub proposal: Option<(MacroBlock, Signature<MacroHeader>)>,
+    // Votes are kept across views, since a locked proposal is re-proposed unchanged.
+    // Prepares are collected per proposal hash and view, commits per proposal hash.
+    pub prepares: HashMap<PbftMessage, HashSet<PbftProof>>,
+    pub commits: HashMap<Hash, HashSet<PbftProof>>,
+    // The prepared proposal we are locked on, kept across views.
+    pub locked: Option<Box<LockedProposal>>,
 
     pub phase: MacroBlockPhase,
 }
 
 impl MacroBlockState {
     pub fn add_prepare(&mut self, prepare: PbftProof) {
-        self.prepares.insert(prepare);
+        self.prepares.entry(prepare.signature.message().clone()).or_default().insert(prepare);
     }
 
     pub fn has_prepare(&mut self, prepare: &PbftProof) -> bool {
-        self.prepares.contains(prepare)
+        self.prepares.get(prepare.signature.message()).map(|set| set.contains(prepare)).unwrap_or(false)
     }
 
-    pub fn num_prepares(&self) -> u16 {
-        self.prepares.len() as u16
+    /// Returns the prepares for a proposal cast in the given view.
+    pub fn get_prepares(&self, hash: &Hash, view_number: u16) -> Option<&HashSet<PbftProof>> {
+        self.prepares.get(&PbftMessage::Prepare(hash.clone(), view_number))
     }
 
-    pub fn add_commit(&mut self, prepare: PbftProof) {
-        self.commits.insert(prepare);
+    pub fn num_prepares(&self, hash: &Hash, view_number: u16) -> u16 {
+        self.get_prepares(hash, view_number).map(HashSet::len).unwrap_or(0) as u16
     }
 
-    pub fn has_commit(&mut self, prepare: &PbftProof) -> bool {
-        self.commits.contains(prepare)
+    pub fn add_commit(&mut self, commit: PbftProof) {
+        self.commits.entry(commit.hash().clone()).or_default().insert(commit);
     }
 
-    pub fn num_commits(&self) -> u16 {
-        self.commits.len() as u16
+    pub fn has_commit(&mut self, commit: &PbftProof) -> bool {
+        self.commits.get(commit.hash()).map(|set| set.contains(commit)).unwrap_or(false)
+    }
+
+    pub fn num_commits(&self, hash: &Hash) -> u16 {
+        self.commits.get(hash).map(HashSet::len).unwrap_or(0) as u16
+    }
+
+    /// Returns the hash of the current proposal.
+    pub fn proposal_hash(&self) -> Option<Hash> {
+        self.proposal.as_ref().map(|(proposal, _)| proposal.header.hash())
+    }
+
+    /// Moves on to the next view, but keeps the lock and the votes.
+    pub fn next_view(&mut self) {
+        self.proposal = None;
+        self.phase = MacroBlockPhase::WAITING;
     }
 
     pub fn reset(&mut self) {
@@ -50,6 +76,7 @@ impl MacroBlockState {
         self.proposal = None;
         self.prepares.clear();
         self.commits.clear();
+        self.locked = None;
         self.phase = MacroBlockPhase::WAITING;
     }
 }
@@ -59,8 +86,9 @@ impl Default for MacroBlockState {
         MacroBlockState {
             view_number: 0,
             proposal: None,
-            prepares: HashSet::new(),
-            commits: HashSet::new(),
+            prepares: HashMap::new(),
+            commits: HashMap::new(),
+            locked: None,
             phase: MacroBlockPhase::WAITING,
         }
     }
@@ -1,29 +1,241 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::str::FromStr;
 use std::time::Duration;
 
+use simulator::Environment;
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+
+use simulator::UniqueId;
+
+use crate::actors::Timing;
+use crate::datastructures::block::Block;
+use crate::datastructures::block::MacroBlock;
+use crate::datastructures::block::MacroHeader;
+use crate::datastructures::hash::Hasher;
+use crate::datastructures::pbft::HandelContribution;
+use crate::datastructures::pbft::PbftMessage;
+use crate::datastructures::pbft::PbftProof;
+use crate::datastructures::pbft::PrepareCertificate;
 use crate::datastructures::pbft::ViewChange;
+use crate::datastructures::signature::KeyPair;
+use crate::datastructures::signature::Signature;
 use crate::datastructures::slashing::SlashInherent;
+use crate::datastructures::sync::BlockRequest;
+use crate::protocol::honest_protocol::HonestProtocol;
+use crate::protocol::macro_block::MacroBlockPhase;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
 
+pub mod chain_store;
+pub mod handel;
 pub mod macro_block;
 pub mod honest_protocol;
+pub mod light_protocol;
+
+/// The handlers a consensus protocol provides to the actor running it.
+/// Handlers for messages that a protocol does not use default to ignoring them.
+pub trait Protocol: Send {
+    /// Starts the protocol when the simulation begins.
+    fn init(&mut self, env: &mut Environment<Event, MetricsEventType>);
+
+    /// Number of the last block we accepted.
+    fn current_block_number(&self) -> u32;
+
+    // External events.
+    fn received_block(&mut self, block: Block, from: UniqueId, env: &mut Environment<Event, MetricsEventType>);
+
+    // Synchronisation.
+    fn handle_block_request(&mut self, _request: &BlockRequest, _from: UniqueId, _env: &mut Environment<Event, MetricsEventType>) {}
+    fn handle_block_response(&mut self, _request: &BlockRequest, _blocks: Vec<Block>, _env: &mut Environment<Event, MetricsEventType>) {}
+
+    // Consensus.
+    fn handle_view_change(&mut self, view_change: ViewChange, env: &mut Environment<Event, MetricsEventType>);
+    fn handle_macro_block_proposal(&mut self, proposal: MacroBlock, signature: Signature<MacroHeader>, certificate: Option<Box<PrepareCertificate>>, env: &mut Environment<Event, MetricsEventType>);
+    fn handle_prepare(&mut self, prepare: PbftProof, env: &mut Environment<Event, MetricsEventType>);
+    fn handle_commit(&mut self, commit: PbftProof, env: &mut Environment<Event, MetricsEventType>);
+    fn handle_contribution(&mut self, _contribution: HandelContribution, _env: &mut Environment<Event, MetricsEventType>) {}
+
+    /// Relays a message gossiped among validators.
+    /// Returns whether the wrapped message is new and should be handled.
+    fn relay_validator_gossip(&mut self, _event: &Event, _from: UniqueId, _env: &mut Environment<Event, MetricsEventType>) -> bool {
+        false
+    }
+
+    // Internal events.
+    fn processed_block(&mut self, block: Block, env: &mut Environment<Event, MetricsEventType>);
+    fn produced_block(&mut self, block: Block, env: &mut Environment<Event, MetricsEventType>);
+    fn processed_proposal(&mut self, proposal: MacroBlock, signature: Signature<MacroHeader>, certificate: Option<Box<PrepareCertificate>>, env: &mut Environment<Event, MetricsEventType>);
+    fn processed_contribution(&mut self, _contribution: HandelContribution, _env: &mut Environment<Event, MetricsEventType>) {}
+
+    // Timeouts.
+    fn handle_production_due(&mut self, _block_number: u32, _view_number: u16, _env: &mut Environment<Event, MetricsEventType>) {}
+    fn handle_timeout(&mut self, block_number: u32, view_number: u16, env: &mut Environment<Event, MetricsEventType>);
+    fn handle_macro_block_timeout(&mut self, block_number: u32, view_number: u16, phase: MacroBlockPhase, env: &mut Environment<Event, MetricsEventType>);
+    fn handle_view_change_timeout(&mut self, _block_number: u32, _view_number: u16, _env: &mut Environment<Event, MetricsEventType>) {}
+    fn handle_handel_timeout(&mut self, _message: PbftMessage, _env: &mut Environment<Event, MetricsEventType>) {}
+    fn handle_request_timeout(&mut self, _id: u32, _env: &mut Environment<Event, MetricsEventType>) {}
+}
+
+/// The consensus protocols that validators can run.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProtocolVariant {
+    /// Albatross with micro blocks and PBFT-style macro blocks.
+    #[default]
+    Albatross,
+}
+
+impl ProtocolVariant {
+    /// Creates an instance of the protocol for a single node.
+    pub fn create(self, protocol_config: ProtocolConfig, timing: Timing,
+                  genesis_block: MacroBlock, key_pair: KeyPair, clock_skew: i64) -> Box<dyn Protocol> {
+        match self {
+            ProtocolVariant::Albatross => Box::new(HonestProtocol::new(protocol_config, timing,
+                                                                        genesis_block, key_pair, clock_skew)),
+        }
+    }
+}
+
+impl FromStr for ProtocolVariant {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "albatross" => Ok(ProtocolVariant::Albatross),
+            _ => Err(()),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct ProtocolConfig {
+    pub variant: ProtocolVariant,
     pub micro_block_timeout: Duration,
     pub macro_block_timeout: Duration,
     pub num_micro_blocks: u32,
     pub num_validators: u16,
+    pub dissemination: Dissemination,
+    pub aggregation: Aggregation,
+    /// Interval at which Handel sends aggregates, also the delay after which the next level is started.
+    pub handel_period: Duration,
+    /// Producers wait at least this long after the timestamp of the previous block.
+    pub min_block_time: Duration,
+    /// Blocks with a timestamp further in the future are rejected.
+    pub max_timestamp_drift: Duration,
+    /// Determines how timeouts grow with the view number.
+    pub backoff: Backoff,
+    /// Timeouts never exceed this duration, if set.
+    pub max_timeout: Option<Duration>,
 }
 
 impl ProtocolConfig {
-    fn max_malicious(&self) -> u16 {
+    pub fn max_malicious(&self) -> u16 {
         (self.num_validators - 1) / 3
     }
 
     pub fn two_third_threshold(&self) -> u16 {
         2 * self.max_malicious() + 1
     }
+
+    /// Returns the timeout in the given view, starting from the timeout `base` of view 0.
+    pub fn timeout(&self, base: Duration, view_number: u16) -> Duration {
+        let timeout = self.backoff.timeout(base, view_number);
+        match self.max_timeout {
+            Some(max_timeout) => timeout.min(max_timeout),
+            None => timeout,
+        }
+    }
+}
+
+/// Determines how PBFT and view change messages are disseminated.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dissemination {
+    /// Send messages to all peers, regardless of whether they are validators.
+    #[default]
+    Flood,
+    /// Send messages directly to all validators we are connected to.
+    /// If some validators cannot be reached directly, gossip messages over the validator overlay.
+    Validators,
+}
+
+impl FromStr for Dissemination {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flood" => Ok(Dissemination::Flood),
+            "validators" => Ok(Dissemination::Validators),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Determines how prepare and commit messages are aggregated.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Aggregation {
+    /// Every validator sends its vote to all other validators.
+    #[default]
+    AllToAll,
+    /// Validators aggregate votes along a binary tree in the style of Handel.
+    Handel,
+}
+
+impl FromStr for Aggregation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all-to-all" => Ok(Aggregation::AllToAll),
+            "handel" => Ok(Aggregation::Handel),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Determines how block timeouts grow with every view change.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backoff {
+    /// The timeout is the same in every view.
+    Constant,
+    /// The timeout grows linearly with the view number.
+    #[default]
+    Linear,
+    /// The timeout doubles with every view change.
+    Exponential,
+    /// The micro block timeout is derived from the observed block times and grows linearly.
+    Adaptive,
+}
+
+impl Backoff {
+    /// Returns the uncapped timeout in the given view.
+    pub fn timeout(self, base: Duration, view_number: u16) -> Duration {
+        match self {
+            Backoff::Constant => base,
+            Backoff::Linear | Backoff::Adaptive => base * (u32::from(view_number) + 1),
+            Backoff::Exponential => 1u32.checked_shl(view_number.into())
+                .and_then(|factor| base.checked_mul(factor))
+                .unwrap_or(Duration::MAX),
+        }
+    }
+}
+
+impl FromStr for Backoff {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "constant" => Ok(Backoff::Constant),
+            "linear" => Ok(Backoff::Linear),
+            "exponential" => Ok(Backoff::Exponential),
+            "adaptive" => Ok(Backoff::Adaptive),
+            _ => Err(()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -32,23 +244,36 @@ pub enum BlockError {
     InvalidBlockNumber,
     InvalidBlockProducer,
     InvalidSignature,
+    InvalidSeed,
     MissingViewChangeMessages,
     InvalidViewChangeMessages,
     OldViewChangeNumber,
-    MicroBlockFork(SlashInherent),
+    MicroBlockFork(Box<SlashInherent>),
+    InvalidSlashInherent,
     MissingJustification,
+    FutureTimestamp,
+    OldTimestamp,
+    BlockTimeTooShort,
+    UnknownParent,
+    InvalidParentHash,
+    InvalidParentMacroHash,
+    InvalidPrepareCertificate,
+    LockedOnOtherProposal,
+    InvalidExtrinsicsRoot,
 }
 
 #[derive(Default)]
 pub struct ViewChangeState {
     pub view_number: u16,
+    // The latest view we sent a view change message for.
+    pub sent_view_number: u16,
     pub view_change_messages: HashMap<u16, HashSet<ViewChange>>,
 }
 
 impl ViewChangeState {
     pub fn add_message(&mut self, view_change: ViewChange) {
         self.view_change_messages.entry(view_change.internals.new_view_number)
-            .or_insert_with(HashSet::new)
+            .or_default()
             .insert(view_change);
     }
 
@@ -58,8 +283,21 @@ impl ViewChangeState {
             .unwrap_or(0) as u16
     }
 
-    pub fn reset(&mut self) {
-        self.view_number = 0;
-        self.view_change_messages.clear();
+    /// Blocks from earlier views are not accepted anymore,
+    /// since we either saw a quorum of view change messages or sent one ourselves.
+    pub fn min_view_number(&self) -> u16 {
+        u16::max(self.view_number, self.sent_view_number)
     }
 }
+
+/// Returns the index of the producer of the block following `previous_block` in the given view,
+/// computed from the seed of `previous_block`.
+pub fn producer_index(previous_block: &Block, view_number: u16, num_validators: usize) -> usize {
+    // H(S || i)
+    let r = Hasher::default()
+        .chain(previous_block.seed().to_bytes())
+        .chain(view_number.to_be_bytes())
+        .result();
+    let r: BigUint = BigUint::from_bytes_be(r.as_ref()) % num_validators;
+    r.to_usize().unwrap()
+}
@@ -0,0 +1,77 @@
+use rand::distributions::{Distribution, WeightedError, WeightedIndex};
+use rand::Rng;
+
+use crate::actors::Timing;
+use crate::simulation::settings::TimingSettings;
+use crate::simulation::topology_helper::AdvancedTopologyHelper;
+
+/// The hardware profiles nodes are assigned to, each with its own timing.
+#[derive(Clone)]
+pub struct HardwareProfiles {
+    pub names: Vec<String>,
+    pub timings: Vec<Timing>,
+    distribution: WeightedIndex<f64>,
+    // The profile pinned to each region of the topology, if any.
+    region_profiles: Vec<Option<usize>>,
+}
+
+impl HardwareProfiles {
+    pub(crate) fn from_settings(settings: &TimingSettings, topology_helper: &AdvancedTopologyHelper) -> Result<Self, Error> {
+        let timing = Timing::from_settings(&settings.signatures);
+
+        // Without profiles, all nodes share the measured timing.
+        if settings.hardware.profiles.is_empty() {
+            return Ok(HardwareProfiles {
+                names: vec!["default".to_string()],
+                timings: vec![timing],
+                distribution: WeightedIndex::new([1.0])?,
+                region_profiles: vec![None; topology_helper.regions.len()],
+            });
+        }
+
+        // The distribution determines the indices, pinned profiles are appended if not contained.
+        let mut names = settings.hardware.profiles.clone();
+        for profile_name in settings.hardware.region_profiles.values() {
+            if !names.contains(profile_name) {
+                names.push(profile_name.clone());
+            }
+        }
+
+        // Existence of profiles is checked by the invariants of TimingSettings.
+        let timings = names.iter()
+            .map(|name| timing.scaled(settings.profiles[name].cost_factor))
+            .collect();
+
+        let mut region_profiles = vec![None; topology_helper.regions.len()];
+        for (region_name, profile_name) in settings.hardware.region_profiles.iter() {
+            let region = topology_helper.regions.iter()
+                .position(|region| &region.name == region_name)
+                .ok_or_else(|| Error::RegionMissing(region_name.clone()))?;
+            region_profiles[region] = names.iter().position(|name| name == profile_name);
+        }
+
+        Ok(HardwareProfiles {
+            names,
+            timings,
+            distribution: WeightedIndex::new(&settings.hardware.profile_distribution)?,
+            region_profiles,
+        })
+    }
+
+    /// Returns the profile of a node in the given region.
+    pub fn sample<R: Rng + ?Sized>(&self, region: usize, rng: &mut R) -> usize {
+        self.region_profiles[region].unwrap_or_else(|| self.distribution.sample(rng))
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    WeightedIndexError(WeightedError),
+    RegionMissing(String),
+}
+
+impl From<WeightedError> for Error {
+    fn from(e: WeightedError) -> Self {
+        Error::WeightedIndexError(e)
+    }
+}
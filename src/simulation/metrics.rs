@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::f64::consts::SQRT_2;
 use std::fmt;
 use std::ops::Div;
 use std::time::Duration;
@@ -16,9 +18,48 @@ pub enum MetricsEventType {
     MessageEvent {
         own: usize,
         from: usize,
-        event: Event,
+        event: Box<Event>,
+    },
+    MacroBlockAccepted(Box<Block>),
+    Rebranch {
+        own: usize,
+        depth: u32,
+    },
+    CaughtUp {
+        own: usize,
+        latency: Duration,
+    },
+    MacroHeaderAccepted {
+        own: usize,
+        hash: Hash,
+    },
+    HeadChanged {
+        own: usize,
+        hash: Hash,
+    },
+    /// A producer sent a second block for the same block number and view.
+    Equivocation {
+        own: usize,
+        block: Box<Block>,
+    },
+    /// The adversary corrupted the upcoming producer.
+    Corrupted {
+        own: usize,
+        block_number: u32,
+        view_number: u16,
+    },
+    /// A producer withheld its block before releasing it.
+    Withheld {
+        own: usize,
+        hash: Hash,
+        delay: Duration,
+    },
+    /// An attacker dropped a message to the victim of an eclipse.
+    Eclipsed {
+        own: usize,
+        victim: usize,
+        message_type: &'static str,
     },
-    MacroBlockAccepted(Block),
 }
 
 impl fmt::Display for MetricsEventType {
@@ -30,17 +71,76 @@ impl fmt::Display for MetricsEventType {
             MetricsEventType::MacroBlockAccepted(block) => {
                 write!(f, "Macro block accepted {}", block)
             },
+            MetricsEventType::Rebranch { own, depth } => {
+                write!(f, "{} rebranched with depth {}", own, depth)
+            },
+            MetricsEventType::CaughtUp { own, latency } => {
+                write!(f, "{} caught up after {:?}", own, latency)
+            },
+            MetricsEventType::MacroHeaderAccepted { own, hash } => {
+                write!(f, "{} accepted macro header {:?}", own, hash)
+            },
+            MetricsEventType::HeadChanged { own, hash } => {
+                write!(f, "{} changed head to {:?}", own, hash)
+            },
+            MetricsEventType::Equivocation { own, block } => {
+                write!(f, "{} equivocated with {}", own, block)
+            },
+            MetricsEventType::Corrupted { own, block_number, view_number } => {
+                write!(f, "{} corrupted for block #{} in view {}", own, block_number, view_number)
+            },
+            MetricsEventType::Withheld { own, hash, delay } => {
+                write!(f, "{} withheld block {:?} for {:?}", own, hash, delay)
+            },
+            MetricsEventType::Eclipsed { own, victim, message_type } => {
+                write!(f, "{} dropped {} to {}", own, message_type, victim)
+            },
         }
     }
 }
 
+/// An equivocation and the block that each node adopted at its block number.
+pub struct EquivocationRecord {
+    pub block_number: u32,
+    pub time: Time,
+    pub branches: HashMap<UniqueId, Hash>,
+    /// The time at which all nodes adopted the same block.
+    pub resolved: Option<Time>,
+}
+
 /// A default metrics implementation.
+#[derive(Default)]
 pub struct DefaultMetrics {
-    pub block_ids: HashMap<u32, Hash>,
     pub block_types: HashMap<Hash, BlockType>,
     pub block_productions: HashMap<Hash, Time>,
+    pub block_timestamps: HashMap<Hash, u64>,
+    pub block_producers: HashMap<Hash, UniqueId>,
+    pub block_parents: HashMap<Hash, (u32, Hash)>,
+    pub slash_inherents: HashMap<Hash, usize>,
+    pub view_change_blocks: HashSet<Hash>,
     pub block_receives: HashMap<Hash, HashMap<UniqueId, Time>>,
     pub proposal_accepted: HashMap<Hash, Time>,
+    pub committed_macro_blocks: HashMap<u32, HashSet<Hash>>,
+    pub message_counts: HashMap<&'static str, usize>,
+    pub message_bytes: HashMap<&'static str, usize>,
+    pub rebranch_depths: Vec<u32>,
+    pub catch_up_latencies: Vec<Duration>,
+    pub light_nodes: HashSet<UniqueId>,
+    pub light_message_counts: usize,
+    pub light_message_bytes: usize,
+    pub light_header_accepts: HashMap<Hash, Vec<Time>>,
+    pub heads: HashMap<UniqueId, Hash>,
+    pub equivocations: Vec<EquivocationRecord>,
+    pub withheld_blocks: HashSet<Hash>,
+    /// The validators corrupted adaptively, once per slot.
+    pub corruptions: Vec<UniqueId>,
+    /// The messages dropped by attackers before reaching eclipsed nodes, by type.
+    pub eclipsed_messages: HashMap<&'static str, usize>,
+    /// The number of full nodes, excluding light nodes.
+    pub num_nodes: usize,
+    pub num_validators: usize,
+    /// Whether to test the sequence of block producers for uniformity.
+    pub producer_statistics: bool,
 }
 
 impl Metrics for DefaultMetrics {
@@ -50,21 +150,40 @@ impl Metrics for DefaultMetrics {
         trace!("Event {}", event);
 
         match event {
-            MetricsEventType::MessageEvent { own, event, .. } => {
-                match event {
+            MetricsEventType::MessageEvent { own, event, from } => {
+                // Count messages received over the network.
+                if own != from {
+                    *self.message_counts.entry(event.message_type()).or_default() += 1;
+                    *self.message_bytes.entry(event.message_type()).or_default() += event.byte_size();
+
+                    if self.light_nodes.contains(own) {
+                        self.light_message_counts += 1;
+                        self.light_message_bytes += event.byte_size();
+                    }
+                }
+
+                match **event {
                     Event::BlockProduced(ref block) => {
                         let hash = block.hash();
                         self.block_types.insert(hash.clone(), block.block_type());
                         self.block_productions.insert(hash.clone(), time);
-
-                        // Overwrites ids.
-                        self.block_ids.insert(block.block_number(), hash);
+                        self.block_timestamps.insert(hash.clone(), block.timestamp());
+                        self.block_parents.insert(hash.clone(), (block.block_number(), block.parent_hash().clone()));
+                        if let Block::Micro(ref micro_block) = block {
+                            self.slash_inherents.insert(hash.clone(), micro_block.extrinsics.slash_inherents.len());
+                        }
+                        if block.view_number() > 0 {
+                            self.view_change_blocks.insert(hash.clone());
+                        }
+                        if self.producer_statistics {
+                            self.block_producers.insert(hash.clone(), *own);
+                        }
                     },
                     Event::BlockProcessed(ref block) => {
                         let hash = block.hash();
                         // Only note first receive.
                         self.block_receives.entry(hash)
-                            .or_insert_with(HashMap::new)
+                            .or_default()
                             .entry(*own)
                             .or_insert(time);
                     },
@@ -73,9 +192,43 @@ impl Metrics for DefaultMetrics {
             }
             MetricsEventType::MacroBlockAccepted(ref block) => {
                 let hash = block.hash();
+                self.committed_macro_blocks.entry(block.block_number())
+                    .or_default()
+                    .insert(hash.clone());
                 // Overwrite and only store last accepted.
                 self.proposal_accepted.insert(hash, time);
             },
+            MetricsEventType::Rebranch { depth, .. } => {
+                self.rebranch_depths.push(*depth);
+            },
+            MetricsEventType::CaughtUp { latency, .. } => {
+                self.catch_up_latencies.push(*latency);
+            },
+            MetricsEventType::MacroHeaderAccepted { hash, .. } => {
+                self.light_header_accepts.entry(hash.clone()).or_default().push(time);
+            },
+            MetricsEventType::HeadChanged { own, hash } => {
+                self.heads.insert(*own, hash.clone());
+                self.update_equivocations(*own, time);
+            },
+            MetricsEventType::Equivocation { block, .. } => {
+                self.block_parents.insert(block.hash(), (block.block_number(), block.parent_hash().clone()));
+                self.equivocations.push(EquivocationRecord {
+                    block_number: block.block_number(),
+                    time,
+                    branches: HashMap::new(),
+                    resolved: None,
+                });
+            },
+            MetricsEventType::Corrupted { own, .. } => {
+                self.corruptions.push(*own);
+            },
+            MetricsEventType::Withheld { hash, .. } => {
+                self.withheld_blocks.insert(hash.clone());
+            },
+            MetricsEventType::Eclipsed { message_type, .. } => {
+                *self.eclipsed_messages.entry(message_type).or_default() += 1;
+            },
         }
     }
 }
@@ -85,7 +238,16 @@ impl DefaultMetrics {
         // Metrics of interest are:
         // - block propagation times (produced to last receive)
         // - macro block proposal to accept time
+        // - conflicting macro blocks (safety violations)
         // - micro block time (time between production of micro blocks)
+        // - micro block time according to the block timestamps
+        // - number of blocks produced after a view change and the time to recover
+        // - time until the network agrees on a block after an equivocation, included slashes
+        // - bandwidth used per message type
+        // - number and depth of rebranches
+        // - catch-up latency of nodes that fell behind
+        // - finality latency and bandwidth of light nodes
+        // - uniformity of block producers (optional)
 
         let propagation_times: Vec<Duration> = self.block_types.iter()
             .filter_map(|(hash, ty)| {
@@ -97,16 +259,7 @@ impl DefaultMetrics {
             })
             .collect();
 
-        if !propagation_times.is_empty() {
-            let min = propagation_times.iter().min().unwrap();
-            let max = propagation_times.iter().max().unwrap();
-            let avg = propagation_times.iter()
-                .fold(Duration::default(), |a, b| a + *b).div(propagation_times.len() as u32);
-
-            info!("Micro block propagation time [min/avg/max]: {:?} {:?} {:?}", min, avg, max);
-        } else {
-            warn!("Empty propagation times!");
-        }
+        log_durations("Micro block propagation time", &propagation_times);
 
         let macro_accept_times: Vec<Duration> = self.block_types.iter().filter_map(|(hash, ty)| {
             if *ty == BlockType::Macro {
@@ -116,15 +269,14 @@ impl DefaultMetrics {
             }
         }).collect();
 
-        if !macro_accept_times.is_empty() {
-            let min = macro_accept_times.iter().min().unwrap();
-            let max = macro_accept_times.iter().max().unwrap();
-            let avg = macro_accept_times.iter()
-                .fold(Duration::default(), |a, b| a + *b).div(macro_accept_times.len() as u32);
+        log_durations("Macro block accept time", &macro_accept_times);
 
-            info!("Macro block accept time [min/avg/max]: {:?} {:?} {:?}", min, avg, max);
-        } else {
-            warn!("Empty macro accept times!");
+        let mut conflicts: Vec<(&u32, &HashSet<Hash>)> = self.committed_macro_blocks.iter()
+            .filter(|(_, hashes)| hashes.len() > 1)
+            .collect();
+        conflicts.sort_by_key(|(block_number, _)| **block_number);
+        for (block_number, hashes) in conflicts {
+            error!("Safety violation: {} different macro blocks committed at #{}", hashes.len(), block_number);
         }
 
         let micro_production_times = self.sorted_micro_production_times();
@@ -133,15 +285,157 @@ impl DefaultMetrics {
             micro_production_windows.push(micro_production_times[i] - micro_production_times[i - 1]);
         }
 
-        if !micro_production_windows.is_empty() {
-            let min = micro_production_windows.iter().min().unwrap();
-            let max = micro_production_windows.iter().max().unwrap();
-            let avg = micro_production_windows.iter()
-                .fold(Duration::default(), |a, b| a + *b).div(micro_production_windows.len() as u32);
+        log_durations("Micro block time", &micro_production_windows);
+
+        let micro_timestamp_windows = self.micro_timestamp_windows();
+        log_durations("Micro block time (timestamps)", &micro_timestamp_windows);
+
+        info!("Blocks produced after a view change: {}", self.view_change_blocks.len());
+        if !self.view_change_blocks.is_empty() {
+            log_durations("View change recovery time", &self.view_change_recovery_times());
+        }
+
+        if !self.equivocations.is_empty() {
+            let split_durations: Vec<Duration> = self.equivocations.iter()
+                .filter_map(|equivocation| Some(equivocation.resolved? - equivocation.time))
+                .collect();
+            info!("Equivocations: {}, {} of them resolved", self.equivocations.len(), split_durations.len());
+            log_durations("Equivocation split duration", &split_durations);
+
+            let slashes: usize = self.main_chain().values()
+                .filter_map(|hash| self.slash_inherents.get(hash))
+                .sum();
+            info!("Slash inherents on the main chain: {}", slashes);
+        }
+
+        if !self.corruptions.is_empty() {
+            let validators: HashSet<&UniqueId> = self.corruptions.iter().collect();
+            info!("Adaptive corruptions: {} slots of {} validators", self.corruptions.len(), validators.len());
+        }
+
+        if !self.withheld_blocks.is_empty() {
+            let included = self.main_chain().values()
+                .filter(|hash| self.withheld_blocks.contains(hash))
+                .count();
+            info!("Withheld blocks: {}, {} of them on the main chain", self.withheld_blocks.len(), included);
+        }
+
+        let mut eclipsed_types: Vec<&&'static str> = self.eclipsed_messages.keys().collect();
+        eclipsed_types.sort();
+        for message_type in eclipsed_types {
+            info!("Messages of type {} dropped by eclipse attackers: {}", message_type, self.eclipsed_messages[message_type]);
+        }
+
+        let mut message_types: Vec<&&'static str> = self.message_counts.keys().collect();
+        message_types.sort();
+        for message_type in message_types {
+            info!("Messages of type {}: {} ({} bytes)", message_type,
+                  self.message_counts[message_type], self.message_bytes[message_type]);
+        }
+        info!("Messages in total: {} ({} bytes)",
+              self.message_counts.values().sum::<usize>(), self.message_bytes.values().sum::<usize>());
 
-            info!("Micro block time [min/avg/max]: {:?} {:?} {:?}", min, avg, max);
+        if !self.rebranch_depths.is_empty() {
+            let min = self.rebranch_depths.iter().min().unwrap();
+            let max = self.rebranch_depths.iter().max().unwrap();
+            let avg = self.rebranch_depths.iter().sum::<u32>() as f64 / self.rebranch_depths.len() as f64;
+            info!("Rebranches: {}, depth [min/avg/max]: {} {:.2} {}", self.rebranch_depths.len(), min, avg, max);
         } else {
-            warn!("Empty micro block times!");
+            info!("Rebranches: 0");
+        }
+
+        info!("Catch-ups: {}", self.catch_up_latencies.len());
+        if !self.catch_up_latencies.is_empty() {
+            log_durations("Catch-up latency", &self.catch_up_latencies);
+        }
+
+        if !self.light_nodes.is_empty() {
+            let light_finality_times: Vec<Duration> = self.light_header_accepts.iter()
+                .filter_map(|(hash, accepts)| {
+                    let produced = self.block_productions.get(hash)?;
+                    Some(accepts.iter().map(move |accepted| *accepted - *produced))
+                })
+                .flatten()
+                .collect();
+            log_durations("Light node finality time", &light_finality_times);
+
+            info!("Messages received per light node: {:.2} ({:.2} bytes)",
+                  self.light_message_counts as f64 / self.light_nodes.len() as f64,
+                  self.light_message_bytes as f64 / self.light_nodes.len() as f64);
+        }
+
+        if self.producer_statistics {
+            self.analyze_producers();
+        }
+    }
+
+    /// Checks safety before and liveness after the global stabilisation time `gst`.
+    pub fn analyze_synchrony(&self, gst: Time) {
+        let mut conflicts_before_gst = 0;
+        for hashes in self.committed_macro_blocks.values().filter(|hashes| hashes.len() > 1) {
+            let first_accept = hashes.iter().filter_map(|hash| self.proposal_accepted.get(hash)).min();
+            if first_accept.is_some_and(|&time| time < gst) {
+                conflicts_before_gst += 1;
+            }
+        }
+        info!("Safety violations before GST: {}", conflicts_before_gst);
+
+        let main_chain = self.main_chain();
+        let (before, after): (Vec<&Hash>, Vec<&Hash>) = main_chain.values()
+            .filter(|hash| self.block_productions.contains_key(hash))
+            .partition(|hash| self.block_productions[hash] < gst);
+        info!("Blocks on the main chain produced before GST: {}, after GST: {}", before.len(), after.len());
+
+        let first_macro_block = after.iter()
+            .filter(|hash| self.block_types.get(hash) == Some(&BlockType::Macro))
+            .filter_map(|hash| self.proposal_accepted.get(hash))
+            .min();
+        match first_macro_block {
+            Some(&time) => info!("First macro block accepted {:?} after GST", time - gst),
+            None => warn!("No macro block accepted after GST"),
+        }
+    }
+
+    /// Tests whether the producers of the chain are uniformly distributed across validators
+    /// using Pearson's chi-square test.
+    fn analyze_producers(&self) {
+        let main_chain = self.main_chain();
+        let mut block_numbers: Vec<&u32> = main_chain.keys().collect();
+        block_numbers.sort();
+        let sequence: Vec<UniqueId> = block_numbers.iter()
+            .filter_map(|block_number| self.block_producers.get(&main_chain[block_number]))
+            .cloned()
+            .collect();
+        debug!("Producer sequence: {:?}", sequence);
+
+        if sequence.is_empty() || self.num_validators < 2 {
+            warn!("Not enough blocks or validators to test producer uniformity!");
+            return;
+        }
+
+        let mut counts: HashMap<UniqueId, usize> = HashMap::new();
+        for producer in sequence.iter() {
+            *counts.entry(*producer).or_default() += 1;
+        }
+
+        let expected = sequence.len() as f64 / self.num_validators as f64;
+        // Validators that never produced a block each contribute `expected`.
+        let num_idle = self.num_validators.saturating_sub(counts.len());
+        let statistic = counts.values()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum::<f64>() + num_idle as f64 * expected;
+        let degrees_of_freedom = self.num_validators - 1;
+        let p_value = chi_square_p_value(statistic, degrees_of_freedom as f64);
+
+        let min = if num_idle > 0 { 0 } else { *counts.values().min().unwrap() };
+        let max = *counts.values().max().unwrap();
+        info!("Block producers: {} blocks, per validator [min/avg/max]: {} {:.2} {}", sequence.len(), min, expected, max);
+        info!("Producer uniformity: chi-square {:.2} with {} degrees of freedom, p-value {:.4}", statistic, degrees_of_freedom, p_value);
+
+        if expected < 5.0 {
+            warn!("Less than 5 blocks per validator, the uniformity test is unreliable!");
+        } else if p_value < 0.001 {
+            warn!("Block producers are not uniformly distributed across validators!");
         }
     }
 
@@ -154,7 +448,7 @@ impl DefaultMetrics {
     fn sorted_micro_production_times(&self) -> Vec<Time> {
         let mut times: Vec<Time> = self.block_productions.iter().filter_map(|(hash, time)| {
             match self.block_types.get(hash).unwrap() {
-                BlockType::Micro => Some(time.clone()),
+                BlockType::Micro => Some(*time),
                 _ => None,
             }
         }).collect();
@@ -162,6 +456,92 @@ impl DefaultMetrics {
         times
     }
 
+    /// Computes the differences between the timestamps of micro blocks on the main chain and their predecessors.
+    fn micro_timestamp_windows(&self) -> Vec<Duration> {
+        let main_chain = self.main_chain();
+        main_chain.iter().filter_map(|(&block_number, hash)| {
+            if block_number == 0 || self.block_types.get(hash) != Some(&BlockType::Micro) {
+                return None;
+            }
+
+            let previous = self.block_timestamps.get(main_chain.get(&(block_number - 1))?)?;
+            let current = self.block_timestamps.get(hash)?;
+            Some(Duration::from_millis(current.saturating_sub(*previous)))
+        }).collect()
+    }
+
+    /// Returns the blocks of the main chain by block number.
+    /// The main chain ends in the head most nodes ended up on, ties are broken by block number.
+    fn main_chain(&self) -> HashMap<u32, Hash> {
+        let mut num_nodes: HashMap<&Hash, usize> = HashMap::new();
+        for head in self.heads.values() {
+            *num_nodes.entry(head).or_default() += 1;
+        }
+        let head = num_nodes.into_iter()
+            .max_by_key(|&(hash, count)| (count, self.block_parents.get(hash).map(|(number, _)| *number), hash));
+
+        let mut main_chain = HashMap::new();
+        let mut hash = match head {
+            Some((hash, _)) => hash,
+            None => return main_chain,
+        };
+        while let Some((number, parent)) = self.block_parents.get(hash) {
+            main_chain.insert(*number, hash.clone());
+            hash = parent;
+        }
+        main_chain
+    }
+
+    /// Returns the ancestor of a block at the given block number.
+    fn ancestor_at<'a>(&'a self, mut hash: &'a Hash, block_number: u32) -> Option<&'a Hash> {
+        loop {
+            let (number, parent) = self.block_parents.get(hash)?;
+            if *number <= block_number {
+                return if *number == block_number { Some(hash) } else { None };
+            }
+            hash = parent;
+        }
+    }
+
+    /// Updates the branch a node follows for each unresolved equivocation.
+    /// An equivocation is resolved once all nodes adopted the same block at its block number.
+    fn update_equivocations(&mut self, own: UniqueId, time: Time) {
+        let head = &self.heads[&own];
+        let mut branches = Vec::new();
+        for (i, equivocation) in self.equivocations.iter().enumerate() {
+            if equivocation.resolved.is_none() {
+                if let Some(branch) = self.ancestor_at(head, equivocation.block_number) {
+                    branches.push((i, branch.clone()));
+                }
+            }
+        }
+
+        let num_nodes = self.num_nodes;
+        for (i, branch) in branches {
+            let equivocation = &mut self.equivocations[i];
+            equivocation.branches.insert(own, branch);
+            let mut adopted = equivocation.branches.values();
+            let first = adopted.next();
+            if equivocation.branches.len() == num_nodes && adopted.all(|branch| Some(branch) == first) {
+                equivocation.resolved = Some(time);
+            }
+        }
+    }
+
+    /// Computes the time between the production of blocks on the main chain after a view change and their predecessors.
+    fn view_change_recovery_times(&self) -> Vec<Duration> {
+        let main_chain = self.main_chain();
+        main_chain.iter().filter_map(|(&block_number, hash)| {
+            if block_number == 0 || !self.view_change_blocks.contains(hash) {
+                return None;
+            }
+
+            let previous = self.block_productions.get(main_chain.get(&(block_number - 1))?)?;
+            let current = self.block_productions.get(hash)?;
+            Some(*current - *previous)
+        }).collect()
+    }
+
     fn macro_accept_time(&self, hash: &Hash) -> Option<Duration> {
         let produced = self.block_productions.get(hash)?;
         let last_receive = self.proposal_accepted.get(hash)?;
@@ -169,14 +549,34 @@ impl DefaultMetrics {
     }
 }
 
-impl Default for DefaultMetrics {
-    fn default() -> Self {
-        DefaultMetrics {
-            block_ids: HashMap::new(),
-            block_types: HashMap::new(),
-            block_productions: HashMap::new(),
-            block_receives: HashMap::new(),
-            proposal_accepted: HashMap::new(),
-        }
+/// Logs the minimum, average and maximum of the given durations.
+fn log_durations(description: &str, durations: &[Duration]) {
+    if !durations.is_empty() {
+        let min = durations.iter().min().unwrap();
+        let max = durations.iter().max().unwrap();
+        let avg = durations.iter()
+            .fold(Duration::default(), |a, b| a + *b).div(durations.len() as u32);
+
+        info!("{} [min/avg/max]: {:?} {:?} {:?}", description, min, avg, max);
+    } else {
+        warn!("Empty {}!", description.to_lowercase());
     }
 }
+
+/// Approximates the probability that a chi-square distributed variable exceeds `statistic`
+/// using the Wilson-Hilferty transformation.
+fn chi_square_p_value(statistic: f64, degrees_of_freedom: f64) -> f64 {
+    let variance = 2.0 / (9.0 * degrees_of_freedom);
+    let z = ((statistic / degrees_of_freedom).cbrt() - (1.0 - variance)) / variance.sqrt();
+    0.5 * erfc(z / SQRT_2)
+}
+
+/// Complementary error function with a fractional error below 1.2e-7 (Numerical Recipes).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t * (-z * z - 1.265_512_23 + t * (1.000_023_68 + t * (0.374_091_96 + t * (0.096_784_18
+        + t * (-0.186_288_06 + t * (0.278_868_07 + t * (-1.135_203_98 + t * (1.488_515_87
+        + t * (-0.822_152_23 + t * 0.170_872_77))))))))).exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
@@ -1,17 +1,26 @@
 use std::fmt;
 
 use crate::datastructures::block::Block;
+use crate::datastructures::ByteSize;
 use crate::datastructures::block::MacroBlock;
 use crate::datastructures::block::MacroHeader;
+use crate::datastructures::pbft::HandelContribution;
+use crate::datastructures::pbft::PbftMessage;
 use crate::datastructures::pbft::PbftProof;
+use crate::datastructures::pbft::PrepareCertificate;
 use crate::datastructures::pbft::ViewChange;
 use crate::datastructures::signature::Signature;
+use crate::datastructures::sync::BlockRequest;
+use crate::datastructures::sync::FinalizedHeader;
 use crate::datastructures::transaction::Transaction;
 use crate::protocol::macro_block::MacroBlockPhase;
 
+pub mod hardware;
 pub mod metrics;
 pub mod network;
+pub mod scenario;
 pub mod settings;
+pub mod synchrony;
 pub mod topology_helper;
 
 #[derive(Clone, Debug)]
@@ -20,26 +29,96 @@ pub enum Event {
     Block(Block),
     Transaction(Transaction),
 
+    // Synchronisation.
+    BlockRequest(BlockRequest),
+    /// The blocks answering a request, which is repeated to match the response to it.
+    BlockResponse(BlockRequest, Vec<Block>),
+    /// Finalised macro block headers sent to light nodes.
+    MacroHeaders(Vec<FinalizedHeader>),
+
     // PBFT.
     ViewChange(ViewChange),
-    BlockProposal(MacroBlock, Signature<MacroHeader>),
+    /// A proposal and, if a locked proposal is re-proposed in a later view, its prepare certificate.
+    BlockProposal(MacroBlock, Signature<MacroHeader>, Option<Box<PrepareCertificate>>),
     BlockPrepare(PbftProof),
     BlockCommit(PbftProof),
+    /// An aggregate of prepare or commit messages sent over Handel.
+    HandelContribution(HandelContribution),
+    /// A PBFT message that is gossiped over the validator overlay,
+    /// because its sender is not directly connected to all validators.
+    ValidatorGossip(Box<Event>),
 
     // Internal events.
     BlockProcessed(Block),
     BlockProduced(Block),
-    ProposalProcessed(MacroBlock, Signature<MacroHeader>),
+    ProposalProcessed(MacroBlock, Signature<MacroHeader>, Option<Box<PrepareCertificate>>),
+    MacroHeaderProcessed(FinalizedHeader),
+    HandelContributionProcessed(HandelContribution),
+    HandelTimeout(PbftMessage),
     TransactionProcessed(Transaction),
+    /// The minimum block time has passed and the block of the given number and view can be produced.
+    BlockProductionDue(u32, u16),
     MicroBlockTimeout(u32, u16),
+    /// The block request with the given id has not been answered in time.
+    BlockRequestTimeout(u32),
     MacroBlockTimeout(u32, u16, MacroBlockPhase),
+    /// The view change to the given view for the block of the given number has not succeeded in time.
+    ViewChangeTimeout(u32, u16),
 
     Init,
 }
 
 impl Event {
     pub fn byte_size(&self) -> usize {
-        0
+        match self {
+            // External events.
+            Event::Block(block) => block.byte_size(),
+            Event::Transaction(transaction) => transaction.byte_size(),
+
+            // Synchronisation.
+            Event::BlockRequest(request) => request.byte_size(),
+            Event::BlockResponse(request, blocks) => request.byte_size() + blocks.byte_size(),
+            Event::MacroHeaders(headers) => headers.byte_size(),
+
+            // PBFT.
+            Event::ViewChange(view_change) => view_change.byte_size(),
+            Event::BlockProposal(proposal, signature, certificate) => proposal.byte_size() + signature.byte_size() + certificate.byte_size(),
+            Event::BlockPrepare(proof) | Event::BlockCommit(proof) => proof.byte_size(),
+            Event::HandelContribution(contribution) => contribution.byte_size(),
+            Event::ValidatorGossip(event) => event.byte_size(),
+
+            // Internal events are never sent over the network.
+            _ => 0,
+        }
+    }
+
+    /// Returns a short name for the type of message, which is used to aggregate metrics.
+    pub fn message_type(&self) -> &'static str {
+        match self {
+            // External events.
+            Event::Block(Block::Micro(_)) => "micro block",
+            Event::Block(Block::Macro(_)) => "macro block",
+            Event::Transaction(_) => "transaction",
+
+            // Synchronisation.
+            Event::BlockRequest(_) => "block request",
+            Event::BlockResponse(..) => "block response",
+            Event::MacroHeaders(_) => "macro headers",
+
+            // PBFT.
+            Event::ViewChange(_) => "view change",
+            Event::BlockProposal(_, _, _) => "proposal",
+            Event::BlockPrepare(_) => "prepare",
+            Event::BlockCommit(_) => "commit",
+            Event::HandelContribution(contribution) => match contribution.message {
+                PbftMessage::Prepare(..) => "aggregate prepare",
+                PbftMessage::Commit(_) => "aggregate commit",
+            },
+            Event::ValidatorGossip(event) => event.message_type(),
+
+            // Internal events.
+            _ => "internal",
+        }
     }
 }
 
@@ -50,18 +129,31 @@ impl fmt::Display for Event {
             Event::Block(block) => write!(f, "received block {}", block),
             Event::Transaction(_transaction) => write!(f, "received transaction"),
 
+            // Synchronisation.
+            Event::BlockRequest(request) => write!(f, "received request for {}", request),
+            Event::BlockResponse(_, blocks) => write!(f, "received {} requested blocks", blocks.len()),
+            Event::MacroHeaders(headers) => write!(f, "received {} macro headers", headers.len()),
+
             // PBFT.
             Event::ViewChange(view_change) => write!(f, "received view change {}", view_change),
-            Event::BlockProposal(proposal, _signature) => write!(f, "received macro block proposal {}", proposal),
+            Event::BlockProposal(proposal, _signature, _certificate) => write!(f, "received macro block proposal {}", proposal),
             Event::BlockPrepare(proof) => write!(f, "received prepare from {}", proof),
             Event::BlockCommit(proof) => write!(f, "received commit from {}", proof),
+            Event::HandelContribution(contribution) => write!(f, "received {}", contribution),
+            Event::ValidatorGossip(event) => write!(f, "{} (gossip)", event),
 
             // Internal events.
             Event::BlockProcessed(block) => write!(f, "processed block {}", block),
             Event::BlockProduced(block) => write!(f, "produced block {}", block),
-            Event::ProposalProcessed(block, _signature) => write!(f, "processed proposal {}", block),
+            Event::ProposalProcessed(block, _signature, _certificate) => write!(f, "processed proposal {}", block),
+            Event::MacroHeaderProcessed(header) => write!(f, "processed macro header {}", header),
+            Event::HandelContributionProcessed(contribution) => write!(f, "processed {}", contribution),
+            Event::HandelTimeout(message) => write!(f, "Handel timeout {:?}", message),
             Event::TransactionProcessed(_transaction) => write!(f, "processed transaction"),
+            Event::BlockProductionDue(block_number, view_number) => write!(f, "production due [#{}, view {}]", block_number, view_number),
             Event::MicroBlockTimeout(block_number, view_number) | Event::MacroBlockTimeout(block_number, view_number, _) => write!(f, "timeout [#{}, view {}]", block_number, view_number),
+            Event::ViewChangeTimeout(block_number, view_number) => write!(f, "view change timeout [#{}, view {}]", block_number, view_number),
+            Event::BlockRequestTimeout(id) => write!(f, "block request {} timed out", id),
 
             Event::Init => write!(f, "initialised"),
         }
@@ -71,4 +163,6 @@ impl fmt::Display for Event {
 #[derive(Clone, Debug)]
 pub struct SimulationConfig {
     pub blocks: u32,
+    /// Light nodes are added to the network in addition to the full nodes.
+    pub num_light_nodes: usize,
 }
@@ -1,22 +1,38 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 
 use rand::distributions::Distribution;
 use rand::distributions::Uniform;
 use rand::Rng;
+use rand::seq::SliceRandom;
 
 use simulator::NetworkConfig;
 use simulator::Node;
 
+use crate::actors::byzantine::ByzantineActor;
+use crate::actors::coordinated::Adversary;
+use crate::actors::coordinated::CoordinatedActor;
+use crate::actors::crash::CrashActor;
+use crate::actors::eclipse::EclipseActor;
+use crate::actors::equivocating::EquivocatingActor;
 use crate::actors::honest::HonestActor;
+use crate::actors::light::LightActor;
 use crate::actors::Timing;
+use crate::actors::withholding::WithholdingActor;
 use crate::datastructures::block::MacroBlock;
 use crate::datastructures::signature::KeyPair;
 use crate::protocol::ProtocolConfig;
 use crate::simulation::Event;
+use crate::simulation::hardware::HardwareProfiles;
 use crate::simulation::metrics::MetricsEventType;
+use crate::simulation::scenario::Behavior;
+use crate::simulation::scenario::Eclipse;
+use crate::simulation::scenario::EclipseFilter;
+use crate::simulation::scenario::Scenario;
 use crate::simulation::SimulationConfig;
 use crate::simulation::topology_helper::AdvancedTopologyHelper;
 
@@ -54,7 +70,7 @@ impl NetworkConfig for SimpleNetwork {
         self.num_nodes
     }
 
-    fn adjacent(&self, from: usize) -> Cow<Vec<usize>> {
+    fn adjacent(&self, from: usize) -> Cow<'_, Vec<usize>> {
         Cow::Owned((0..self.num_nodes).filter(|i| *i != from).collect::<Vec<usize>>())
     }
 
@@ -66,10 +82,10 @@ impl NetworkConfig for SimpleNetwork {
         }
     }
 
-    fn node(&self, id: usize) -> Box<Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>> {
+    fn node(&self, id: usize) -> Box<dyn Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>> {
         Box::new(HonestActor::new(self.simulation_config.clone(),
                                   self.protocol_config.clone(), self.timing.clone(),
-                                  self.genesis_block.clone(), KeyPair::from_id(id as u64 )))
+                                  self.genesis_block.clone(), KeyPair::from_id(id as u64 ), 0))
     }
 }
 
@@ -78,15 +94,28 @@ pub struct AdvancedNetwork {
     links: Vec<HashMap<usize, LinkConfig>>,
     simulation_config: SimulationConfig,
     protocol_config: ProtocolConfig,
-    timing: Timing,
-    genesis_block: MacroBlock
+    // The timing of each hardware profile.
+    timings: Vec<Timing>,
+    genesis_block: MacroBlock,
+    // The adversary shared by all corrupted validators.
+    adversary: Arc<Mutex<Adversary>>,
+    // The victims of an eclipse and what the attackers pass on to them.
+    victims: HashSet<usize>,
+    eclipse_filter: Option<EclipseFilter>,
 }
 
 struct NodeConfig {
     download_bandwidth: f64, // Mbps
     upload_bandwidth: f64, // Mbps
     region: usize,
+    profile: usize,
+    clock_skew: i64, // ms
     connections: Vec<usize>,
+    light: bool,
+    attacker: bool,
+    behavior: Behavior,
+    // Whether the adversary watches the node to corrupt it adaptively.
+    watched: bool,
 }
 
 struct LinkConfig {
@@ -98,20 +127,35 @@ impl AdvancedNetwork {
     pub(crate) fn new<R: Rng + ?Sized>(num_nodes: usize, topology_helper: &AdvancedTopologyHelper,
                                                         simulation_config: SimulationConfig,
                                                         protocol_config: ProtocolConfig,
-                                                        timing: Timing,
+                                                        hardware: &HardwareProfiles,
+                                                        scenario: &Scenario,
                                                         rng: &mut R) -> Self {
         let mut nodes = Vec::new();
 
-        debug!("Create {} nodes.", num_nodes);
-        // Assign nodes to regions and estimate their bandwidths.
-        for _ in 0..num_nodes {
+        let num_light_nodes = simulation_config.num_light_nodes;
+        let num_attackers = scenario.eclipse().map_or(0, Eclipse::attackers);
+        debug!("Create {} nodes, {} light nodes and {} attackers.", num_nodes, num_light_nodes, num_attackers);
+        // Assign nodes to regions and hardware profiles and estimate their bandwidths.
+        // Light nodes are appended after the full nodes, attackers after the light nodes.
+        for node_id in 0..num_nodes + num_light_nodes + num_attackers {
             let region = topology_helper.nodes_distribution.sample(rng);
+            let clock_skew = if topology_helper.max_clock_skew > 0.0 {
+                rng.gen_range(-topology_helper.max_clock_skew, topology_helper.max_clock_skew).round() as i64
+            } else {
+                0
+            };
 
             nodes.push(NodeConfig {
                 region,
+                profile: hardware.sample(region, rng),
                 download_bandwidth: topology_helper.regions[region].download_bandwidth_distribution.sample(rng),
                 upload_bandwidth: topology_helper.regions[region].upload_bandwidth_distribution.sample(rng),
+                clock_skew,
                 connections: Vec::new(),
+                light: node_id >= num_nodes && node_id < num_nodes + num_light_nodes,
+                attacker: node_id >= num_nodes + num_light_nodes,
+                behavior: Behavior::Honest,
+                watched: false,
             });
         }
 
@@ -123,17 +167,73 @@ impl AdvancedNetwork {
             validators.insert(uniform_node_distribution.sample(rng));
         }
 
+        for (profile, name) in hardware.names.iter().enumerate() {
+            let profile_nodes = nodes.iter().filter(|node| node.profile == profile).count();
+            let profile_validators = validators.iter().filter(|&&id| nodes[id].profile == profile).count();
+            info!("Hardware profile {}: {} nodes, {} of them validators.", name, profile_nodes, profile_validators);
+        }
+
+        // Behaviours refer to validators by their index in the genesis block.
+        let genesis_block = MacroBlock::create_genesis_block(&validators);
+        let validator_ids: Vec<usize> = genesis_block.header.digest.validators.iter()
+            .map(|key| key.id() as usize)
+            .collect();
+        let behaviors = scenario.assign(&validator_ids, rng);
+        for (&validator_id, behavior) in behaviors.iter() {
+            nodes[validator_id].behavior = behavior.clone();
+        }
+        let corrupted: Vec<usize> = validator_ids.iter()
+            .cloned()
+            .filter(|&id| matches!(nodes[id].behavior, Behavior::Coordinated))
+            .collect();
+        if scenario.adaptive_budget() > 0 {
+            for &validator_id in validator_ids.iter() {
+                nodes[validator_id].watched = matches!(nodes[validator_id].behavior, Behavior::Honest);
+            }
+        }
+        let adversary = Adversary::new(scenario.script().to_vec(), protocol_config.clone(),
+                                       &genesis_block, &corrupted, scenario.adaptive_budget());
+
+        let victims: HashSet<usize> = match scenario.eclipse() {
+            Some(eclipse) => {
+                let other_nodes: Vec<usize> = (0..num_nodes).filter(|id| !validators.contains(id)).collect();
+                eclipse.victims(&validator_ids, &behaviors, &other_nodes, rng).into_iter().collect()
+            },
+            None => HashSet::new(),
+        };
+
         debug!("Interconnect validators.");
         // Interconnect all validators.
+        // Eclipsed validators have their slots filled by attackers instead.
         for &validator_id in validators.iter() {
             for &connection in validators.iter() {
                 // Do not connect to oneself.
-                if validator_id != connection {
+                if validator_id != connection && !victims.contains(&validator_id) && !victims.contains(&connection) {
                     nodes[validator_id].connections.push(connection);
                 }
             }
         }
 
+        if let Some(eclipse) = scenario.eclipse() {
+            debug!("Connect attackers.");
+            // Attackers take the connection slots of their victims first.
+            // Victims only sample honest connections if this leaves them with less than their minimum.
+            let attackers: Vec<usize> = (num_nodes + num_light_nodes..nodes.len()).collect();
+            for &victim in victims.iter() {
+                let max_connections = if validators.contains(&victim) {
+                    topology_helper.max_connections_per_validator
+                } else {
+                    topology_helper.max_connections_per_node
+                };
+
+                let slots = eclipse.slots().unwrap_or(max_connections);
+                for &attacker in attackers.choose_multiple(rng, slots) {
+                    nodes[victim].connections.push(attacker);
+                    nodes[attacker].connections.push(victim);
+                }
+            }
+        }
+
         debug!("Sample random connections.");
         // Sample random other connections.
         for node_id in 0..num_nodes {
@@ -170,10 +270,62 @@ impl AdvancedNetwork {
             }
         }
 
+        let attackers = num_nodes + num_light_nodes..nodes.len();
+        if !attackers.is_empty() {
+            debug!("Connect attackers to honest nodes.");
+            // Attackers connect to the remaining slots of honest full nodes to learn what the victims could see.
+            for attacker in attackers {
+                let min_connections = nodes[attacker].connections.len() + topology_helper.min_connections_per_node;
+                let mut tries = 0;
+                while nodes[attacker].connections.len() < min_connections && tries < 3 {
+                    let connection = uniform_node_distribution.sample(rng);
+                    tries += 1;
+
+                    let max_connections_peer = if validators.contains(&connection) {
+                        topology_helper.max_connections_per_validator
+                    } else {
+                        topology_helper.max_connections_per_node
+                    };
+
+                    if !victims.contains(&connection) && !nodes[attacker].connections.contains(&connection)
+                        && nodes[connection].connections.len() < max_connections_peer {
+                        tries = 0;
+
+                        nodes[attacker].connections.push(connection);
+                        nodes[connection].connections.push(attacker);
+                    }
+                }
+            }
+        }
+
+        debug!("Connect light nodes.");
+        // Light nodes only connect to full nodes.
+        for node_id in num_nodes..num_nodes + num_light_nodes {
+            let mut tries = 0;
+            while nodes[node_id].connections.len() < topology_helper.min_connections_per_node && tries < 3 {
+                let connection = uniform_node_distribution.sample(rng);
+                tries += 1;
+
+                let max_connections_peer = if validators.contains(&connection) {
+                    topology_helper.max_connections_per_validator
+                } else {
+                    topology_helper.max_connections_per_node
+                };
+
+                if !nodes[node_id].connections.contains(&connection)
+                    && nodes[connection].connections.len() < max_connections_peer {
+                    tries = 0;
+
+                    nodes[node_id].connections.push(connection);
+                    nodes[connection].connections.push(node_id);
+                }
+            }
+        }
+
         debug!("Sample link configuration.");
         // Then sample link configurations.
         let mut links: Vec<HashMap<usize, LinkConfig>> = Vec::new();
-        for node_id in 0..num_nodes {
+        for node_id in 0..nodes.len() {
             let mut link_configs = HashMap::new();
             for &peer_id in nodes[node_id].connections.iter() {
                 // Only add them once.
@@ -198,17 +350,22 @@ impl AdvancedNetwork {
             links.push(link_configs);
         }
 
-        let genesis_block = MacroBlock::create_genesis_block(&validators);
-
         AdvancedNetwork {
             nodes,
             links,
             simulation_config,
             protocol_config,
-            timing,
+            timings: hardware.timings.clone(),
             genesis_block,
+            adversary: Arc::new(Mutex::new(adversary)),
+            victims,
+            eclipse_filter: scenario.eclipse().map(|eclipse| eclipse.filter().clone()),
         }
     }
+
+    pub fn genesis_block(&self) -> &MacroBlock {
+        &self.genesis_block
+    }
 }
 
 impl NetworkConfig for AdvancedNetwork {
@@ -219,7 +376,7 @@ impl NetworkConfig for AdvancedNetwork {
         self.nodes.len()
     }
 
-    fn adjacent(&self, from: usize) -> Cow<Vec<usize>> {
+    fn adjacent(&self, from: usize) -> Cow<'_, Vec<usize>> {
         Cow::Borrowed(&self.nodes[from].connections)
     }
 
@@ -231,7 +388,7 @@ impl NetworkConfig for AdvancedNetwork {
             // size / bandwidth + latency
             let size = (event.byte_size() * 8 /* bits */) as f64;
             let link_config = self.links.get(usize::min(from, to))?.get(&usize::max(from, to))?;
-            let bandwidth = link_config.bandwidth * 100 /* Mbps -> bits per ms */ as f64;
+            let bandwidth = link_config.bandwidth * 1000.0 /* Mbps -> bits per ms */;
             let delay: f64 = size / bandwidth + link_config.latency; // ms
             Some(Duration::from_millis(delay.ceil() as u64))
         } else {
@@ -239,9 +396,35 @@ impl NetworkConfig for AdvancedNetwork {
         }
     }
 
-    fn node(&self, id: usize) -> Box<Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>> {
-        Box::new(HonestActor::new(self.simulation_config.clone(),
-                                  self.protocol_config.clone(), self.timing.clone(),
-                                  self.genesis_block.clone(), KeyPair::from_id(id as u64 )))
+    fn node(&self, id: usize) -> Box<dyn Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>> {
+        let timing = self.timings[self.nodes[id].profile].clone();
+        if self.nodes[id].light {
+            return Box::new(LightActor::new(self.protocol_config.clone(), timing,
+                                            self.genesis_block.clone()));
+        }
+        if let Some(ref filter) = self.eclipse_filter {
+            if self.nodes[id].attacker {
+                return Box::new(EclipseActor::new(self.victims.clone(), filter.clone()));
+            }
+        }
+
+        let actor = HonestActor::new(self.simulation_config.clone(),
+                                     self.protocol_config.clone(), timing,
+                                     self.genesis_block.clone(), KeyPair::from_id(id as u64 ),
+                                     self.nodes[id].clock_skew);
+        match self.nodes[id].behavior {
+            Behavior::Honest if self.nodes[id].watched => Box::new(CoordinatedActor::new(actor, Arc::clone(&self.adversary))),
+            Behavior::Honest => Box::new(actor),
+            Behavior::Crash(ref schedule) => Box::new(CrashActor::new(actor, schedule.clone())),
+            Behavior::Equivocate => Box::new(EquivocatingActor::new(actor, KeyPair::from_id(id as u64))),
+            Behavior::Withhold(ref release) => Box::new(WithholdingActor::new(actor, self.protocol_config.clone(), release.clone())),
+            Behavior::Byzantine(ref attacks) => {
+                let validator_ids = self.genesis_block.header.digest.validators.iter()
+                    .map(|key| key.id() as usize)
+                    .collect();
+                Box::new(ByzantineActor::new(actor, KeyPair::from_id(id as u64), attacks.clone(), validator_ids))
+            },
+            Behavior::Coordinated => Box::new(CoordinatedActor::new(actor, Arc::clone(&self.adversary))),
+        }
     }
 }
@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::protocol::ProtocolConfig;
+use crate::simulation::Event;
+use crate::simulation::settings::ScenarioSettings;
+
+/// How a validator behaves during the simulation.
+#[derive(Clone, Debug, Default)]
+pub enum Behavior {
+    #[default]
+    Honest,
+    Crash(CrashSchedule),
+    Equivocate,
+    Withhold(Release),
+    Byzantine(Vec<PbftAttack>),
+    /// Corrupted by the adversary, following the scenario's script.
+    Coordinated,
+}
+
+impl fmt::Display for Behavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Behavior::Honest => write!(f, "are honest"),
+            Behavior::Crash(CrashSchedule { crash_at, recover_at: None }) => write!(f, "crash after {:?}", crash_at),
+            Behavior::Crash(CrashSchedule { crash_at, recover_at: Some(recover_at) }) => {
+                write!(f, "crash after {:?} and recover after {:?}", crash_at, recover_at)
+            },
+            Behavior::Equivocate => write!(f, "equivocate as producers"),
+            Behavior::Withhold(Release::Delay(delay)) => write!(f, "withhold their blocks for {:?}", delay),
+            Behavior::Withhold(Release::Timeout(TimeoutTarget::BeforeTimeout, margin)) => {
+                write!(f, "withhold their blocks until {:?} before the timeout", margin)
+            },
+            Behavior::Withhold(Release::Timeout(TimeoutTarget::AfterTimeout, margin)) => {
+                write!(f, "withhold their blocks until {:?} after the timeout", margin)
+            },
+            Behavior::Byzantine(attacks) => {
+                let attacks: Vec<String> = attacks.iter().map(PbftAttack::to_string).collect();
+                write!(f, "attack PBFT with {}", attacks.join(", "))
+            },
+            Behavior::Coordinated => write!(f, "are corrupted by the adversary"),
+        }
+    }
+}
+
+/// When a withholding producer releases its micro blocks.
+#[derive(Clone, Debug)]
+pub enum Release {
+    /// A fixed delay after production.
+    Delay(Duration),
+    /// At a distance to the micro block timeout of the block's view.
+    Timeout(TimeoutTarget, Duration),
+}
+
+impl Release {
+    fn from_settings(delay: Option<u64>, target: Option<TimeoutTarget>, margin: u64) -> Self {
+        match target {
+            Some(target) => Release::Timeout(target, Duration::from_micros(margin)),
+            None => Release::Delay(Duration::from_micros(delay.unwrap_or_default())),
+        }
+    }
+
+    /// Returns the delay after production for a block in the given view.
+    /// Peers start their timeout when accepting the previous block,
+    /// which is approximately when the block is produced as long as the minimum block time has passed.
+    pub fn delay(&self, protocol_config: &ProtocolConfig, view_number: u16) -> Duration {
+        match self {
+            Release::Delay(delay) => *delay,
+            Release::Timeout(target, margin) => {
+                let timeout = protocol_config.timeout(protocol_config.micro_block_timeout, view_number);
+                match target {
+                    TimeoutTarget::BeforeTimeout => timeout.saturating_sub(*margin),
+                    TimeoutTarget::AfterTimeout => timeout + *margin,
+                }
+            },
+        }
+    }
+}
+
+/// Whether withheld blocks are released before or after the timeout of peers.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeoutTarget {
+    BeforeTimeout,
+    AfterTimeout,
+}
+
+/// How a Byzantine validator deviates from PBFT.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PbftAttack {
+    /// Prepare and commit every proposal seen, even conflicting ones.
+    ConflictingVotes,
+    /// Propose two different macro blocks to disjoint halves of the validators.
+    DoubleProposal,
+    /// Never prepare or commit.
+    WithholdVotes,
+    /// Prepare and commit the proposals of earlier views instead of the current one.
+    StaleVotes,
+}
+
+impl fmt::Display for PbftAttack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            PbftAttack::ConflictingVotes => write!(f, "conflicting votes"),
+            PbftAttack::DoubleProposal => write!(f, "double proposals"),
+            PbftAttack::WithholdVotes => write!(f, "withheld votes"),
+            PbftAttack::StaleVotes => write!(f, "stale votes"),
+        }
+    }
+}
+
+/// The kind of a strategy in the adversary's script.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrategyKind {
+    Crash,
+    Equivocate,
+    Withhold,
+    SplitVotes,
+}
+
+/// A strategy of the coordinated adversary.
+#[derive(Clone, Debug)]
+pub enum Strategy {
+    /// Corrupted validators are offline.
+    Crash,
+    /// Corrupted producers sign two micro blocks, each sent to the same half of the network by all corrupted nodes.
+    Equivocate,
+    /// Corrupted producers withhold their micro blocks, and no corrupted validator votes to replace a corrupted producer.
+    Withhold(Release),
+    /// Corrupted leaders propose two macro blocks, and all corrupted validators vote for every proposal.
+    SplitVotes,
+}
+
+/// A strategy and the time window in which it is active.
+/// The first active strategy that applies to an event is used.
+#[derive(Clone, Debug)]
+pub struct ScriptedStrategy {
+    pub strategy: Strategy,
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+impl ScriptedStrategy {
+    /// Whether the strategy is active at `elapsed` since the start of the simulation.
+    pub fn is_active(&self, elapsed: Duration) -> bool {
+        elapsed >= self.start && self.end.is_none_or(|end| elapsed < end)
+    }
+}
+
+/// When a crashing validator goes offline and when it comes back, if ever.
+/// Both are relative to the start of the simulation.
+#[derive(Clone, Debug)]
+pub struct CrashSchedule {
+    pub crash_at: Duration,
+    pub recover_at: Option<Duration>,
+}
+
+impl CrashSchedule {
+    /// Whether the validator is offline at `elapsed` since the start of the simulation.
+    pub fn is_down(&self, elapsed: Duration) -> bool {
+        elapsed >= self.crash_at && self.recover_at.is_none_or(|recover_at| elapsed < recover_at)
+    }
+}
+
+/// How attacker nodes pass on messages to the victims of an eclipse.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EclipseMode {
+    Drop,
+    Delay,
+    Filter,
+}
+
+/// What attacker nodes pass on to the victims of an eclipse.
+#[derive(Clone, Debug)]
+pub enum EclipseFilter {
+    /// Nothing at all.
+    Drop,
+    /// Everything, but only after a delay.
+    Delay(Duration),
+    /// Everything except messages of the given types.
+    Filter(Vec<String>),
+}
+
+impl EclipseFilter {
+    /// Returns the delay after which the event is passed on to a victim, or `None` if it is dropped.
+    pub fn delay(&self, event: &Event) -> Option<Duration> {
+        match self {
+            EclipseFilter::Drop => None,
+            EclipseFilter::Delay(delay) => Some(*delay),
+            EclipseFilter::Filter(message_types) => {
+                if message_types.iter().any(|message_type| message_type == event.message_type()) {
+                    None
+                } else {
+                    Some(Duration::default())
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for EclipseFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            EclipseFilter::Drop => write!(f, "drop all messages"),
+            EclipseFilter::Delay(delay) => write!(f, "delay all messages by {:?}", delay),
+            EclipseFilter::Filter(message_types) => write!(f, "drop messages of type {}", message_types.join(", ")),
+        }
+    }
+}
+
+/// Victims whose connection slots are filled by attacker nodes, which control what the victims see.
+#[derive(Clone, Debug)]
+pub struct Eclipse {
+    selection: Option<Selection>,
+    nodes: usize,
+    attackers: usize,
+    slots: Option<usize>,
+    filter: EclipseFilter,
+}
+
+impl Eclipse {
+    /// The number of attacker nodes added to the network.
+    pub fn attackers(&self) -> usize {
+        self.attackers
+    }
+
+    /// The number of connection slots of each victim taken by attackers, if limited.
+    pub fn slots(&self) -> Option<usize> {
+        self.slots
+    }
+
+    pub fn filter(&self) -> &EclipseFilter {
+        &self.filter
+    }
+
+    /// Selects the victims among the validators, which are given by their node ids in the order of the genesis block,
+    /// and among the other full nodes. Validators deviating from the protocol are only eclipsed if selected by index.
+    pub fn victims<R: Rng + ?Sized>(&self, validators: &[usize], behaviors: &HashMap<usize, Behavior>,
+                                    other_nodes: &[usize], rng: &mut R) -> Vec<usize> {
+        let mut victims = match self.selection {
+            Some(ref selection) => {
+                let assigned = validators.iter()
+                    .enumerate()
+                    .filter_map(|(index, id)| behaviors.get(id).map(|behavior| (index, behavior.clone())))
+                    .collect();
+                selection.select(validators.len(), &assigned, rng).into_iter()
+                    .map(|index| validators[index])
+                    .collect()
+            },
+            None => Vec::new(),
+        };
+        let num_validators = victims.len();
+
+        if self.nodes > other_nodes.len() {
+            warn!("Cannot eclipse {} of {} other full nodes.", self.nodes, other_nodes.len());
+        }
+        victims.extend(other_nodes.choose_multiple(rng, self.nodes));
+
+        info!("{} attackers eclipse {} validators and {} other nodes and {}.", self.attackers, num_validators,
+              victims.len() - num_validators, self.filter);
+        victims
+    }
+}
+
+/// Which messages a network adversary delays.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetworkAttack {
+    /// All messages to the producer of the upcoming block.
+    DelayLeader,
+    /// All messages between two groups of nodes, each containing half of the validators.
+    Split,
+    /// All messages by a random delay, which reorders them.
+    Random,
+}
+
+impl fmt::Display for NetworkAttack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            NetworkAttack::DelayLeader => write!(f, "messages to the leader"),
+            NetworkAttack::Split => write!(f, "messages between two halves of the validators"),
+            NetworkAttack::Random => write!(f, "all messages randomly"),
+        }
+    }
+}
+
+/// A network adversary in the partial synchrony model.
+/// Messages sent at time t arrive by max(t, GST) + delta at the latest,
+/// but never earlier than the network allows.
+#[derive(Clone, Debug)]
+pub struct Synchrony {
+    pub gst: Duration,
+    pub delta: Duration,
+    pub attack: NetworkAttack,
+    /// The delay added to attacked messages, the maximum for random delays.
+    pub delay: Duration,
+    /// Seeds the random delays, which are drawn from the system's randomness otherwise.
+    pub seed: Option<u64>,
+}
+
+/// Selects the validators following a behaviour.
+#[derive(Clone, Debug)]
+enum Selection {
+    /// A fraction of all validators, sampled uniformly at random among those that are still honest.
+    Fraction(f64),
+    /// Validators by their index in the validator list of the genesis block.
+    Indices(Vec<usize>),
+}
+
+impl Selection {
+    fn from_settings(fraction: Option<f64>, validators: &Option<Vec<usize>>) -> Self {
+        match validators {
+            Some(indices) => Selection::Indices(indices.clone()),
+            None => Selection::Fraction(fraction.unwrap_or_default()),
+        }
+    }
+
+    /// Returns the indices of the selected validators among `num_validators`,
+    /// excluding those in `assigned` when sampling.
+    fn select<R: Rng + ?Sized>(&self, num_validators: usize, assigned: &HashMap<usize, Behavior>, rng: &mut R) -> Vec<usize> {
+        match self {
+            Selection::Fraction(fraction) => {
+                let amount = (fraction * num_validators as f64).round() as usize;
+                let candidates: Vec<usize> = (0..num_validators)
+                    .filter(|index| !assigned.contains_key(index))
+                    .collect();
+                candidates.choose_multiple(rng, amount).cloned().collect()
+            },
+            Selection::Indices(indices) => indices.iter()
+                .cloned()
+                .filter(|&index| {
+                    if index >= num_validators {
+                        warn!("There is no validator with index {}.", index);
+                    } else if assigned.contains_key(&index) {
+                        warn!("Validator {} is assigned multiple behaviours.", index);
+                    }
+                    index < num_validators
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Determines which validators deviate from the protocol and how.
+#[derive(Clone, Debug, Default)]
+pub struct Scenario {
+    behaviors: Vec<(Selection, Behavior)>,
+    script: Vec<ScriptedStrategy>,
+    adaptive_budget: usize,
+    eclipse: Option<Eclipse>,
+    synchrony: Option<Synchrony>,
+}
+
+impl Scenario {
+    pub(crate) fn from_settings(settings: &ScenarioSettings) -> Self {
+        let mut behaviors = Vec::new();
+
+        if let Some(ref crash) = settings.crash {
+            behaviors.push((Selection::from_settings(crash.fraction, &crash.validators), Behavior::Crash(CrashSchedule {
+                crash_at: Duration::from_micros(crash.crash_at),
+                recover_at: crash.recover_at.map(Duration::from_micros),
+            })));
+        }
+
+        if let Some(ref equivocate) = settings.equivocate {
+            behaviors.push((Selection::from_settings(equivocate.fraction, &equivocate.validators), Behavior::Equivocate));
+        }
+
+        if let Some(ref withhold) = settings.withhold {
+            let release = Release::from_settings(withhold.delay, withhold.target, withhold.margin);
+            behaviors.push((Selection::from_settings(withhold.fraction, &withhold.validators), Behavior::Withhold(release)));
+        }
+
+        if let Some(ref byzantine) = settings.byzantine {
+            behaviors.push((Selection::from_settings(byzantine.fraction, &byzantine.validators), Behavior::Byzantine(byzantine.attacks.clone())));
+        }
+
+        let mut script = Vec::new();
+        let mut adaptive_budget = 0;
+        if let Some(ref adversary) = settings.adversary {
+            if adversary.fraction.is_some() || adversary.validators.is_some() {
+                behaviors.push((Selection::from_settings(adversary.fraction, &adversary.validators), Behavior::Coordinated));
+            }
+            adaptive_budget = adversary.adaptive_budget;
+
+            for strategy in adversary.strategy.iter() {
+                script.push(ScriptedStrategy {
+                    strategy: match strategy.kind {
+                        StrategyKind::Crash => Strategy::Crash,
+                        StrategyKind::Equivocate => Strategy::Equivocate,
+                        StrategyKind::Withhold => Strategy::Withhold(Release::from_settings(strategy.delay, strategy.target, strategy.margin)),
+                        StrategyKind::SplitVotes => Strategy::SplitVotes,
+                    },
+                    start: Duration::from_micros(strategy.start),
+                    end: strategy.end.map(Duration::from_micros),
+                });
+            }
+        }
+
+        let eclipse = settings.eclipse.as_ref().map(|eclipse| Eclipse {
+            selection: if eclipse.fraction.is_some() || eclipse.validators.is_some() {
+                Some(Selection::from_settings(eclipse.fraction, &eclipse.validators))
+            } else {
+                None
+            },
+            nodes: eclipse.nodes,
+            attackers: eclipse.attackers,
+            slots: eclipse.slots,
+            filter: match eclipse.mode {
+                EclipseMode::Drop => EclipseFilter::Drop,
+                EclipseMode::Delay => EclipseFilter::Delay(Duration::from_micros(eclipse.delay.unwrap_or_default())),
+                EclipseMode::Filter => EclipseFilter::Filter(eclipse.filter.clone()),
+            },
+        });
+
+        let synchrony = settings.synchrony.as_ref().map(|synchrony| Synchrony {
+            gst: Duration::from_micros(synchrony.gst),
+            delta: Duration::from_micros(synchrony.delta),
+            attack: synchrony.attack,
+            delay: Duration::from_micros(synchrony.delay),
+            seed: synchrony.seed,
+        });
+
+        Scenario {
+            behaviors,
+            script,
+            adaptive_budget,
+            eclipse,
+            synchrony,
+        }
+    }
+
+    /// The number of slots in which the adversary may corrupt the producer.
+    pub fn adaptive_budget(&self) -> usize {
+        self.adaptive_budget
+    }
+
+    /// The script of the coordinated adversary.
+    pub fn script(&self) -> &[ScriptedStrategy] {
+        &self.script
+    }
+
+    /// The eclipse attack on the network topology, if any.
+    pub fn eclipse(&self) -> Option<&Eclipse> {
+        self.eclipse.as_ref()
+    }
+
+    /// The network adversary, if any.
+    pub fn synchrony(&self) -> Option<&Synchrony> {
+        self.synchrony.as_ref()
+    }
+
+    /// Assigns behaviours to the validators, which are given by their node ids
+    /// in the order of the genesis block. Validators that are not contained are honest.
+    pub fn assign<R: Rng + ?Sized>(&self, validators: &[usize], rng: &mut R) -> HashMap<usize, Behavior> {
+        // Validator indices to behaviours.
+        let mut assigned = HashMap::new();
+
+        for (selection, behavior) in self.behaviors.iter() {
+            let indices = selection.select(validators.len(), &assigned, rng);
+            info!("{} validators {}.", indices.len(), behavior);
+            for index in indices {
+                assigned.insert(index, behavior.clone());
+            }
+        }
+
+        // PBFT tolerates f = (n - 1) / 3 faulty validators.
+        let max_faulty = validators.len().saturating_sub(1) / 3;
+        if assigned.len() > max_faulty {
+            warn!("{} of {} validators deviate from the protocol, more than the {} tolerated.", assigned.len(), validators.len(), max_faulty);
+        }
+
+        assigned.into_iter()
+            .map(|(index, behavior)| (validators[index], behavior))
+            .collect()
+    }
+}
@@ -2,6 +2,16 @@ use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::Path;
 
+use crate::protocol::Aggregation;
+use crate::protocol::Backoff;
+use crate::protocol::Dissemination;
+use crate::protocol::ProtocolVariant;
+use crate::simulation::scenario::EclipseMode;
+use crate::simulation::scenario::NetworkAttack;
+use crate::simulation::scenario::PbftAttack;
+use crate::simulation::scenario::StrategyKind;
+use crate::simulation::scenario::TimeoutTarget;
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 #[serde(deny_unknown_fields)]
@@ -83,6 +93,9 @@ pub(crate) struct MainSettings {
     pub min_connections_per_validator: usize,
     pub max_connections_per_validator: usize,
     pub latency_pareto_shape_divider: f64,
+    /// Clocks are in sync if not set.
+    #[serde(default)]
+    pub max_clock_skew: f64,
 }
 
 #[derive(Clone, Debug, Deserialize, Default)]
@@ -103,10 +116,38 @@ pub(crate) struct SignatureTimingSettings {
 #[serde(rename_all = "kebab-case")]
 #[serde(deny_unknown_fields)]
 pub(crate) struct ProtocolSettings {
+    #[serde(default)]
+    pub protocol: ProtocolVariant,
+
     pub micro_block_timeout: u64,
     pub macro_block_timeout: u64,
+    #[serde(default = "default_max_timestamp_drift")]
+    pub max_timestamp_drift: u64,
+    #[serde(default)]
+    pub min_block_time: u64,
+    #[serde(default)]
+    pub backoff: Backoff,
+    #[serde(default)]
+    pub max_timeout: Option<u64>,
 
     pub num_micro_blocks: u32,
+
+    #[serde(default)]
+    pub dissemination: Dissemination,
+    #[serde(default)]
+    pub aggregation: Aggregation,
+    #[serde(default = "default_handel_period")]
+    pub handel_period: u64,
+}
+
+/// Blocks may be stamped up to 1s ahead of the local clock.
+fn default_max_timestamp_drift() -> u64 {
+    1_000_000
+}
+
+/// Only used with Handel aggregation.
+fn default_handel_period() -> u64 {
+    10_000
 }
 
 impl ProtocolSettings {
@@ -121,21 +162,323 @@ impl ProtocolSettings {
 #[serde(deny_unknown_fields)]
 pub(crate) struct TimingSettings {
     pub signatures: SignatureTimingSettings,
+    #[serde(default)]
+    pub hardware: HardwareSettings,
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileSettings>,
 }
 
 impl TimingSettings {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<TimingSettings, Error> {
         let settings: TimingSettings = toml::from_str(read_to_string(path)?.as_ref())?;
+
+        // Check settings for consistency.
+        // That means:
+        // 1. |hardware.profiles| = |hardware.profile_distribution|
+        if settings.hardware.profiles.len() != settings.hardware.profile_distribution.len() {
+            return Err(Error::SizeMismatch("|hardware.profiles| != |hardware.profile_distribution|".to_string()));
+        }
+
+        // 2. Profiles exist
+        for profile_name in settings.hardware.profiles.iter().chain(settings.hardware.region_profiles.values()) {
+            if !settings.profiles.contains_key(profile_name) {
+                return Err(Error::ProfileMissing(profile_name.clone()));
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Assigns hardware profiles to nodes.
+/// Without profiles, all nodes run on the hardware the signature timings were measured on.
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HardwareSettings {
+    pub profiles: Vec<String>,
+    pub profile_distribution: Vec<f64>,
+    /// Nodes in these regions always use the given profile.
+    #[serde(default)]
+    pub region_profiles: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProfileSettings {
+    /// Factor by which signature costs are scaled.
+    pub cost_factor: f64,
+}
+
+/// Determines which validators deviate from the protocol.
+/// Without any sections, all validators are honest.
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ScenarioSettings {
+    #[serde(default)]
+    pub crash: Option<CrashSettings>,
+    #[serde(default)]
+    pub equivocate: Option<EquivocateSettings>,
+    #[serde(default)]
+    pub withhold: Option<WithholdSettings>,
+    #[serde(default)]
+    pub byzantine: Option<ByzantineSettings>,
+    #[serde(default)]
+    pub adversary: Option<AdversarySettings>,
+    #[serde(default)]
+    pub eclipse: Option<EclipseSettings>,
+    #[serde(default)]
+    pub synchrony: Option<SynchronySettings>,
+}
+
+impl ScenarioSettings {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ScenarioSettings, Error> {
+        let settings: ScenarioSettings = toml::from_str(read_to_string(path)?.as_ref())?;
+
+        // Check settings for consistency.
+        // That means:
+        if let Some(ref crash) = settings.crash {
+            // 1. Validators are selected either by fraction or by index
+            check_selection("crash", crash.fraction, &crash.validators)?;
+
+            // 2. crash.crash_at < crash.recover_at
+            if crash.recover_at.is_some_and(|recover_at| recover_at <= crash.crash_at) {
+                return Err(Error::InvalidValue("crash.recover_at <= crash.crash_at".to_string()));
+            }
+        }
+
+        if let Some(ref equivocate) = settings.equivocate {
+            // 3. Validators are selected either by fraction or by index
+            check_selection("equivocate", equivocate.fraction, &equivocate.validators)?;
+        }
+
+        if let Some(ref withhold) = settings.withhold {
+            // 4. Validators are selected either by fraction or by index
+            check_selection("withhold", withhold.fraction, &withhold.validators)?;
+
+            // 5. Blocks are released either after a delay or relative to the timeout
+            if withhold.delay.is_some() == withhold.target.is_some() {
+                return Err(Error::InvalidValue("exactly one of withhold.delay and withhold.target required".to_string()));
+            }
+        }
+
+        if let Some(ref byzantine) = settings.byzantine {
+            // 6. Validators are selected either by fraction or by index
+            check_selection("byzantine", byzantine.fraction, &byzantine.validators)?;
+
+            // 7. At least one attack is given
+            if byzantine.attacks.is_empty() {
+                return Err(Error::InvalidValue("byzantine.attacks is empty".to_string()));
+            }
+        }
+
+        if let Some(ref adversary) = settings.adversary {
+            // 8. Validators are selected either by fraction or by index, unless they are only corrupted adaptively
+            if adversary.adaptive_budget == 0 || adversary.fraction.is_some() || adversary.validators.is_some() {
+                check_selection("adversary", adversary.fraction, &adversary.validators)?;
+            }
+
+            for strategy in adversary.strategy.iter() {
+                // 9. strategy.start < strategy.end
+                if strategy.end.is_some_and(|end| end <= strategy.start) {
+                    return Err(Error::InvalidValue("adversary.strategy.end <= adversary.strategy.start".to_string()));
+                }
+
+                // 10. Withheld blocks are released either after a delay or relative to the timeout
+                if strategy.kind == StrategyKind::Withhold && strategy.delay.is_some() == strategy.target.is_some() {
+                    return Err(Error::InvalidValue("exactly one of adversary.strategy.delay and adversary.strategy.target required".to_string()));
+                }
+            }
+        }
+
+        if let Some(ref eclipse) = settings.eclipse {
+            // 11. Validators are selected either by fraction or by index, unless only other nodes are eclipsed
+            if eclipse.nodes == 0 || eclipse.fraction.is_some() || eclipse.validators.is_some() {
+                check_selection("eclipse", eclipse.fraction, &eclipse.validators)?;
+            }
+
+            // 12. There is at least one attacker with at least one slot
+            if eclipse.attackers == 0 || eclipse.slots == Some(0) {
+                return Err(Error::InvalidValue("eclipse.attackers or eclipse.slots is 0".to_string()));
+            }
+
+            // 13. The mode is given its parameter
+            match eclipse.mode {
+                EclipseMode::Delay if eclipse.delay.is_none() => {
+                    return Err(Error::InvalidValue("eclipse.delay required for mode delay".to_string()));
+                },
+                EclipseMode::Filter if eclipse.filter.is_empty() => {
+                    return Err(Error::InvalidValue("eclipse.filter required for mode filter".to_string()));
+                },
+                _ => {},
+            }
+        }
+
+        if let Some(ref synchrony) = settings.synchrony {
+            // 14. Messages are delayed at all
+            if synchrony.delay == 0 {
+                return Err(Error::InvalidValue("synchrony.delay is 0".to_string()));
+            }
+        }
+
         Ok(settings)
     }
 }
 
+/// Validators are selected either as a fraction of all validators or by their index in the validator list.
+fn check_selection(section: &str, fraction: Option<f64>, validators: &Option<Vec<usize>>) -> Result<(), Error> {
+    match (fraction, validators) {
+        (Some(fraction), None) if (0.0..=1.0).contains(&fraction) => Ok(()),
+        (Some(_), None) => Err(Error::InvalidValue(format!("{}.fraction not in [0, 1]", section))),
+        (None, Some(_)) => Ok(()),
+        _ => Err(Error::InvalidValue(format!("exactly one of {0}.fraction and {0}.validators required", section))),
+    }
+}
+
+/// Validators that crash at a given time and optionally recover later.
+/// Times are given in µs since the start of the simulation.
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CrashSettings {
+    #[serde(default)]
+    pub fraction: Option<f64>,
+    #[serde(default)]
+    pub validators: Option<Vec<usize>>,
+    pub crash_at: u64,
+    #[serde(default)]
+    pub recover_at: Option<u64>,
+}
+
+/// Validators that sign two different micro blocks whenever they produce one.
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EquivocateSettings {
+    #[serde(default)]
+    pub fraction: Option<f64>,
+    #[serde(default)]
+    pub validators: Option<Vec<usize>>,
+}
+
+/// Validators that produce valid micro blocks, but withhold them
+/// for a fixed delay or until shortly before or after the micro block timeout of their peers.
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WithholdSettings {
+    #[serde(default)]
+    pub fraction: Option<f64>,
+    #[serde(default)]
+    pub validators: Option<Vec<usize>>,
+    #[serde(default)]
+    pub delay: Option<u64>,
+    #[serde(default)]
+    pub target: Option<TimeoutTarget>,
+    /// Distance to the timeout in µs.
+    #[serde(default)]
+    pub margin: u64,
+}
+
+/// Validators that attack the PBFT protocol for macro blocks.
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ByzantineSettings {
+    #[serde(default)]
+    pub fraction: Option<f64>,
+    #[serde(default)]
+    pub validators: Option<Vec<usize>>,
+    pub attacks: Vec<PbftAttack>,
+}
+
+/// Validators that are corrupted by a single adversary, which shares their keys and knowledge.
+/// The adversary follows a script of strategies, each active during a time window.
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AdversarySettings {
+    #[serde(default)]
+    pub fraction: Option<f64>,
+    #[serde(default)]
+    pub validators: Option<Vec<usize>>,
+    /// The number of slots in which the upcoming producer is corrupted.
+    #[serde(default)]
+    pub adaptive_budget: usize,
+    #[serde(default)]
+    pub strategy: Vec<StrategySettings>,
+}
+
+/// A strategy of the adversary, active from `start` until `end` in µs since the start of the simulation.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct StrategySettings {
+    pub kind: StrategyKind,
+    #[serde(default)]
+    pub start: u64,
+    #[serde(default)]
+    pub end: Option<u64>,
+    /// Only for withholding, see `WithholdSettings`.
+    #[serde(default)]
+    pub delay: Option<u64>,
+    #[serde(default)]
+    pub target: Option<TimeoutTarget>,
+    #[serde(default)]
+    pub margin: u64,
+}
+
+/// Victims, selected among the validators and the other full nodes, whose connection slots are filled by attacker nodes.
+/// The attackers pass on nothing (`drop`), everything after `delay` µs (`delay`)
+/// or everything except the message types in `filter` (`filter`) to the victims.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EclipseSettings {
+    #[serde(default)]
+    pub fraction: Option<f64>,
+    #[serde(default)]
+    pub validators: Option<Vec<usize>>,
+    /// The number of other full nodes eclipsed.
+    #[serde(default)]
+    pub nodes: usize,
+    pub attackers: usize,
+    /// The number of connection slots of each victim taken by attackers, defaults to all of them.
+    #[serde(default)]
+    pub slots: Option<usize>,
+    pub mode: EclipseMode,
+    #[serde(default)]
+    pub delay: Option<u64>,
+    #[serde(default)]
+    pub filter: Vec<String>,
+}
+
+/// A network adversary that delays messages arbitrarily until the global stabilisation time `gst`
+/// and respects the bound `delta` afterwards. Times are given in µs.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SynchronySettings {
+    pub gst: u64,
+    pub delta: u64,
+    pub attack: NetworkAttack,
+    /// The delay added to attacked messages, the maximum for random delays.
+    pub delay: u64,
+    /// Seeds the random delays.
+    pub seed: Option<u64>,
+}
+
 #[derive(Debug)]
+#[allow(dead_code)] // Fields are only read through `Debug`.
 pub(crate) enum Error {
     Toml(toml::de::Error),
     Io(std::io::Error),
     SizeMismatch(String),
     RegionMissing(String),
+    ProfileMissing(String),
+    InvalidValue(String),
 }
 
 impl From<toml::de::Error> for Error {
@@ -0,0 +1,189 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use simulator::NetworkConfig;
+use simulator::Node;
+use simulator::UniqueId;
+
+use crate::datastructures::block::Block;
+use crate::datastructures::block::MacroBlock;
+use crate::protocol::producer_index;
+use crate::simulation::Event;
+use crate::simulation::metrics::MetricsEventType;
+use crate::simulation::scenario::NetworkAttack;
+use crate::simulation::scenario::Synchrony;
+
+/// Wraps a network and lets a network adversary delay messages in the partial synchrony model.
+/// Without an adversary, the network is used as is.
+pub struct SynchronyNetwork<N> {
+    network: N,
+    synchrony: Option<Synchrony>,
+    /// The validators in the order of the genesis block.
+    validator_ids: Vec<UniqueId>,
+    /// Validators move on to the next view once they saw more view change messages than this.
+    view_change_threshold: u16,
+    /// The leader as far as the adversary has observed the traffic.
+    leader: Mutex<Leader>,
+    /// Draws the random delays.
+    rng: Mutex<StdRng>,
+}
+
+/// The producer of the upcoming block.
+struct Leader {
+    previous_block: Block,
+    view_number: u16,
+    id: UniqueId,
+    /// The validators that sent a view change for the upcoming block, by new view number.
+    view_changes: HashMap<u16, HashSet<u64>>,
+}
+
+impl<N> SynchronyNetwork<N> {
+    /// Random delays are seeded from the scenario if given, and from `rng` otherwise.
+    pub fn new<R: Rng + ?Sized>(network: N, synchrony: Option<Synchrony>, genesis_block: &MacroBlock,
+                                view_change_threshold: u16, rng: &mut R) -> Self {
+        if let Some(ref synchrony) = synchrony {
+            info!("Network adversary delays {} by {:?} until GST at {:?}, then by at most {:?}.",
+                  synchrony.attack, synchrony.delay, synchrony.gst, synchrony.delta);
+        }
+        let rng = match synchrony.as_ref().and_then(|synchrony| synchrony.seed) {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(rng).unwrap(),
+        };
+
+        let validator_ids: Vec<UniqueId> = genesis_block.header.digest.validators.iter()
+            .map(|key| key.id() as UniqueId)
+            .collect();
+        let previous_block = Block::Macro(genesis_block.clone());
+        let id = validator_ids[producer_index(&previous_block, 0, validator_ids.len())];
+
+        SynchronyNetwork {
+            network,
+            synchrony,
+            validator_ids,
+            view_change_threshold,
+            leader: Mutex::new(Leader {
+                previous_block,
+                view_number: 0,
+                id,
+                view_changes: HashMap::new(),
+            }),
+            rng: Mutex::new(rng),
+        }
+    }
+
+    /// Follows blocks and view changes to learn the upcoming producer and returns it.
+    /// Like the validators, the adversary only moves on to a new view once it saw a quorum of view changes for it.
+    fn observe_leader(&self, event: &Event) -> UniqueId {
+        let mut guard = self.leader.lock().unwrap();
+        let leader = &mut *guard;
+        let next_block_number = leader.previous_block.block_number() + 1;
+
+        let view_change = match event {
+            Event::Block(block) if block.block_number() >= next_block_number => {
+                leader.previous_block = block.clone();
+                leader.view_number = 0;
+                leader.view_changes.clear();
+                None
+            },
+            Event::ViewChange(view_change) => Some(view_change),
+            Event::ValidatorGossip(inner) => match **inner {
+                Event::ViewChange(ref view_change) => Some(view_change),
+                _ => return leader.id,
+            },
+            _ => return leader.id,
+        };
+
+        if let Some(view_change) = view_change {
+            let new_view_number = view_change.internals.new_view_number;
+            if view_change.internals.block_number != next_block_number || new_view_number <= leader.view_number
+                || !view_change.verify() {
+                return leader.id;
+            }
+
+            let signers = leader.view_changes.entry(new_view_number).or_default();
+            signers.insert(view_change.signature.public_key().id());
+            if signers.len() <= usize::from(self.view_change_threshold) {
+                return leader.id;
+            }
+            leader.view_number = new_view_number;
+        }
+
+        let index = producer_index(&leader.previous_block, leader.view_number, self.validator_ids.len());
+        leader.id = self.validator_ids[index];
+        leader.id
+    }
+
+    /// Splits the validators by their index in the genesis block and other nodes by their id.
+    fn in_first_group(&self, id: UniqueId) -> bool {
+        match self.validator_ids.iter().position(|&validator_id| validator_id == id) {
+            Some(index) => index.is_multiple_of(2),
+            None => id.is_multiple_of(2),
+        }
+    }
+
+    /// Returns the delay the adversary would like to add to an event.
+    fn attack_delay(&self, synchrony: &Synchrony, from: UniqueId, to: UniqueId, event: &Event) -> Duration {
+        match synchrony.attack {
+            NetworkAttack::DelayLeader => {
+                if self.observe_leader(event) == to {
+                    synchrony.delay
+                } else {
+                    Duration::default()
+                }
+            },
+            NetworkAttack::Split => {
+                if self.in_first_group(from) != self.in_first_group(to) {
+                    synchrony.delay
+                } else {
+                    Duration::default()
+                }
+            },
+            NetworkAttack::Random => {
+                let delay = self.rng.lock().unwrap().gen_range(0, synchrony.delay.as_micros() as u64 + 1);
+                Duration::from_micros(delay)
+            },
+        }
+    }
+}
+
+impl<N: NetworkConfig<EventType=Event, MetricsEventType=MetricsEventType>> NetworkConfig for SynchronyNetwork<N> {
+    type EventType = Event;
+    type MetricsEventType = MetricsEventType;
+
+    fn num_nodes(&self) -> usize {
+        self.network.num_nodes()
+    }
+
+    fn adjacent(&self, from: usize) -> Cow<'_, Vec<usize>> {
+        self.network.adjacent(from)
+    }
+
+    fn full_transmission_time(&self, from: usize, to: usize, event: &Event) -> Option<Duration> {
+        self.network.full_transmission_time(from, to, event)
+    }
+
+    /// A message sent at `elapsed` arrives by max(elapsed, GST) + delta,
+    /// unless the network itself is slower.
+    fn transmission_time_at(&self, from: usize, to: usize, event: &Event, elapsed: Duration) -> Option<Duration> {
+        let delay = self.network.transmission_time_at(from, to, event, elapsed)?;
+        let synchrony = match self.synchrony {
+            Some(ref synchrony) => synchrony,
+            None => return Some(delay),
+        };
+
+        let bound = synchrony.gst.saturating_sub(elapsed) + synchrony.delta;
+        let attacked = delay + self.attack_delay(synchrony, from, to, event);
+        Some(Duration::max(delay, Duration::min(attacked, bound)))
+    }
+
+    fn node(&self, id: usize) -> Box<dyn Node<EventType=Self::EventType, MetricsEventType=Self::MetricsEventType>> {
+        self.network.node(id)
+    }
+}
@@ -9,6 +9,7 @@ pub struct AdvancedTopologyHelper<'a> {
     pub min_connections_per_validator: usize,
     pub max_connections_per_validator: usize,
     latency_pareto_shape_divider: Option<f64>,
+    pub max_clock_skew: f64,
     pub nodes_distribution: WeightedIndex<f64>,
     pub connections_distribution: PiecewiseConstant<u64, usize, usize>,
     pub regions: Vec<Region<'a>>,
@@ -52,6 +53,7 @@ impl<'a> AdvancedTopologyHelper<'a> {
             min_connections_per_validator: settings.main.min_connections_per_validator,
             max_connections_per_validator: settings.main.max_connections_per_validator,
             latency_pareto_shape_divider: Some(settings.main.latency_pareto_shape_divider),
+            max_clock_skew: settings.main.max_clock_skew,
             nodes_distribution,
             connections_distribution,
             regions,